@@ -23,7 +23,7 @@ struct MyLogger {
 impl MyLogger {
     fn new() -> MyLogger {
         use env_logger::filter::Builder;
-        let mut builder = Builder::from_env("MY_LOG_LEVEL");
+        let builder = Builder::from_env("MY_LOG_LEVEL");
 
         MyLogger {
             inner: builder.build(),
@@ -0,0 +1,365 @@
+//! Capturing log records to assert on in tests.
+//!
+//! [`Builder::is_test`] lets the test harness capture the logger's output, but that
+//! output can't be inspected by the test itself. A [`Capture`] collects each record
+//! logged on the threads it's attached to, so tests can check what was logged.
+//!
+//! Captures are attached to threads rather than the logger, so tests running in
+//! parallel on their own threads only see their own records. Records are only
+//! captured if they match the logger's filters, so the logger needs to be
+//! initialized with a level verbose enough for the records being checked.
+//!
+//! # Examples
+//!
+//! ```
+//! use log::{warn, Level, LevelFilter};
+//!
+//! let _ = env_logger::builder()
+//!     .is_test(true)
+//!     .filter_level(LevelFilter::Trace)
+//!     .try_init();
+//!
+//! let logs = env_logger::capture::start();
+//!
+//! warn!("disk is almost full");
+//!
+//! logs.assert_logged(Level::Warn, "almost full");
+//! logs.assert_not_logged(Level::Error, "full");
+//! ```
+//!
+//! [`Builder::is_test`]: ../struct.Builder.html#method.is_test
+//! [`Capture`]: struct.Capture.html
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use log::{Level, Record};
+
+type Records = Mutex<Vec<CapturedRecord>>;
+
+thread_local! {
+    // The capture isn't kept alive by the thread, so dropping it stops capturing
+    static CURRENT: RefCell<Option<Weak<Records>>> = RefCell::new(None);
+}
+
+/// Starts capturing the records logged on the current thread.
+///
+/// This replaces any capture already attached to the current thread.
+/// See [`Capture`] for more details.
+///
+/// [`Capture`]: struct.Capture.html
+pub fn start() -> Capture {
+    let capture = Capture::new();
+    capture.attach();
+
+    capture
+}
+
+/// A store of captured log records.
+///
+/// Captures can be cloned and sent to other threads. Clones share the same records.
+/// Records stop being captured once every clone has been dropped.
+#[derive(Clone, Default)]
+pub struct Capture {
+    records: Arc<Records>,
+}
+
+/// A log record that was captured.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CapturedRecord {
+    level: Level,
+    target: String,
+    message: String,
+    key_values: Vec<(String, String)>,
+    formatted: String,
+}
+
+impl Capture {
+    /// Creates a capture that isn't attached to any threads.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Captures the records logged on the current thread.
+    ///
+    /// This can be used to capture records from threads spawned by the code being
+    /// tested. It replaces any capture already attached to the current thread.
+    pub fn attach(&self) {
+        let records = Arc::downgrade(&self.records);
+        let _ = CURRENT.try_with(|current| *current.borrow_mut() = Some(records));
+    }
+
+    /// Returns the records captured so far, in the order they were logged.
+    pub fn records(&self) -> Vec<CapturedRecord> {
+        self.lock().clone()
+    }
+
+    /// Discards the records captured so far.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Whether a record at the given level was captured with a message containing `text`.
+    pub fn logged(&self, level: Level, text: &str) -> bool {
+        self.lock()
+            .iter()
+            .any(|record| record.level == level && record.message.contains(text))
+    }
+
+    /// Panics unless a record at the given level was captured with a message containing `text`.
+    ///
+    /// The panic message lists the records that were captured.
+    pub fn assert_logged(&self, level: Level, text: &str) {
+        if !self.logged(level, text) {
+            panic!(
+                "expected a {} record containing `{}`, but captured:\n{}",
+                level,
+                text,
+                self.describe()
+            );
+        }
+    }
+
+    /// Panics if a record at the given level was captured with a message containing `text`.
+    ///
+    /// The panic message lists the records that were captured.
+    pub fn assert_not_logged(&self, level: Level, text: &str) {
+        if self.logged(level, text) {
+            panic!(
+                "expected no {} records containing `{}`, but captured:\n{}",
+                level,
+                text,
+                self.describe()
+            );
+        }
+    }
+
+    fn describe(&self) -> String {
+        let records = self.lock();
+
+        if records.is_empty() {
+            return "  (nothing)".to_owned();
+        }
+
+        records
+            .iter()
+            .map(|record| format!("  [{} {}] {}", record.level, record.target, record.message))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<CapturedRecord>> {
+        // A panic while holding the lock can't leave the records in an invalid state
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Capture")
+            .field("records", &*self.lock())
+            .finish()
+    }
+}
+
+impl CapturedRecord {
+    /// The level of the record.
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// The target of the record.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// The message of the record, without any formatting.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The key-value pairs of the record.
+    ///
+    /// Key-value pairs are only captured with the `kv` feature.
+    pub fn key_values(&self) -> &[(String, String)] {
+        &self.key_values
+    }
+
+    /// The value of the given key, if the record has it.
+    pub fn key_value(&self, key: &str) -> Option<&str> {
+        self.key_values
+            .iter()
+            .find(|&(k, _)| k == key)
+            .map(|(_, v)| &**v)
+    }
+
+    /// The record as it was formatted by the logger.
+    pub fn formatted(&self) -> &str {
+        &self.formatted
+    }
+}
+
+/// Capture a record if a capture is attached to the current thread.
+///
+/// The formatted text is only produced if the record is captured.
+pub(crate) fn capture<F>(record: &Record, formatted: F)
+where
+    F: FnOnce() -> String,
+{
+    let records = CURRENT
+        .try_with(|current| {
+            let mut current = current.borrow_mut();
+            let records = current.as_ref().and_then(Weak::upgrade);
+
+            if records.is_none() {
+                *current = None;
+            }
+
+            records
+        })
+        .ok()
+        .and_then(|records| records);
+
+    if let Some(records) = records {
+        let captured = CapturedRecord {
+            level: record.level(),
+            target: record.target().to_owned(),
+            message: record.args().to_string(),
+            key_values: key_values(record),
+            formatted: formatted(),
+        };
+
+        records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(captured);
+    }
+}
+
+#[cfg(feature = "kv")]
+fn key_values(record: &Record) -> Vec<(String, String)> {
+    use log::kv::{self, Key, Value, Visitor};
+
+    struct Collect(Vec<(String, String)>);
+
+    impl<'kvs> Visitor<'kvs> for Collect {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+            self.0.push((key.to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+
+    let mut collect = Collect(Vec::new());
+    let _ = record.key_values().visit(&mut collect);
+
+    collect.0
+}
+
+#[cfg(not(feature = "kv"))]
+fn key_values(_: &Record) -> Vec<(String, String)> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    fn log(level: Level, target: &str, message: &str) {
+        capture(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{}", message))
+                .build(),
+            || format!("{}: {}\n", level, message),
+        );
+    }
+
+    #[test]
+    fn captures_records_on_attached_thread() {
+        let logs = start();
+
+        log(Level::Warn, "app", "disk is almost full");
+        log(Level::Info, "app::db", "connected");
+
+        let records = logs.records();
+        assert_eq!(2, records.len());
+
+        assert_eq!(Level::Warn, records[0].level());
+        assert_eq!("app", records[0].target());
+        assert_eq!("disk is almost full", records[0].message());
+        assert_eq!("WARN: disk is almost full\n", records[0].formatted());
+
+        logs.assert_logged(Level::Info, "connected");
+        logs.assert_not_logged(Level::Warn, "connected");
+    }
+
+    #[test]
+    fn threads_are_captured_separately() {
+        let logs = start();
+
+        let other = thread::spawn(|| {
+            let logs = start();
+            log(Level::Error, "other", "from another thread");
+
+            logs.records()
+        })
+        .join()
+        .unwrap();
+
+        log(Level::Error, "main", "from this thread");
+
+        assert_eq!(1, other.len());
+        assert_eq!("from another thread", other[0].message());
+
+        logs.assert_logged(Level::Error, "from this thread");
+        logs.assert_not_logged(Level::Error, "from another thread");
+    }
+
+    #[test]
+    fn attach_to_spawned_thread() {
+        let logs = start();
+
+        let attached = logs.clone();
+        thread::spawn(move || {
+            attached.attach();
+            log(Level::Debug, "worker", "working");
+        })
+        .join()
+        .unwrap();
+
+        logs.assert_logged(Level::Debug, "working");
+    }
+
+    #[test]
+    fn dropped_capture_stops_capturing() {
+        let logs = start();
+        drop(logs);
+
+        log(Level::Error, "app", "not captured");
+
+        CURRENT.with(|current| assert!(current.borrow().is_none()));
+    }
+
+    #[test]
+    fn clear_discards_records() {
+        let logs = start();
+
+        log(Level::Error, "app", "a message");
+        logs.clear();
+
+        assert!(logs.records().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a WARN record containing `missing`")]
+    fn assert_logged_panics_when_missing() {
+        let logs = start();
+        log(Level::Info, "app", "a message");
+
+        logs.assert_logged(Level::Warn, "missing");
+    }
+}
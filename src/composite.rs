@@ -0,0 +1,273 @@
+/*
+This internal module contains the composite logger.
+
+The `log` crate only accepts a single global logger, so a composite holds any
+number of env loggers along with other `Log` implementations, and sends each
+record to all of them.
+*/
+
+use std::any::Any;
+use std::fmt;
+use std::panic::Location;
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::{panic, scope, Logger, Stats};
+
+/// A logger that sends records to several loggers.
+///
+/// A composite can hold any number of env loggers, built with [`Builder::build`],
+/// along with any other `Log` implementations, like a metrics counter or a remote
+/// sink. Each record is sent to every logger that's enabled for it.
+///
+/// When it's installed as the global logger, the `log` crate's maximum level is
+/// the most verbose level of any of its loggers. Other `Log` implementations are
+/// given their own maximum level when they're added, and are only sent records
+/// at that level or more severe.
+///
+/// # Examples
+///
+/// ```
+/// use log::{LevelFilter, Log, Metadata, Record};
+/// use env_logger::{Builder, Composite};
+///
+/// struct Metrics;
+///
+/// impl Log for Metrics {
+///     fn enabled(&self, _: &Metadata) -> bool {
+///         true
+///     }
+///
+///     fn log(&self, record: &Record) {
+///         // Count the record
+///     }
+///
+///     fn flush(&self) {}
+/// }
+///
+/// Composite::new()
+///     .logger(Builder::from_default_env().build())
+///     .other(Box::new(Metrics), LevelFilter::Warn)
+///     .init();
+/// ```
+///
+/// [`Builder::build`]: struct.Builder.html#method.build
+#[derive(Default)]
+pub struct Composite {
+    loggers: Vec<Logger>,
+    others: Vec<(Box<dyn Log>, LevelFilter)>,
+}
+
+impl Composite {
+    /// Creates a composite without any loggers.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds an env logger.
+    pub fn logger(mut self, logger: Logger) -> Self {
+        self.loggers.push(logger);
+        self
+    }
+
+    /// Adds another `Log` implementation.
+    ///
+    /// Only records at `level` or more severe are sent to it.
+    pub fn other(mut self, log: Box<dyn Log>, level: LevelFilter) -> Self {
+        self.others.push((log, level));
+        self
+    }
+
+    /// Returns the most verbose `LevelFilter` of any of the loggers.
+    pub fn filter(&self) -> LevelFilter {
+        self.combined(Logger::filter)
+    }
+
+    /// Returns the statistics of all the env loggers, added together.
+    ///
+    /// See [`Stats`] for more details.
+    ///
+    /// [`Stats`]: struct.Stats.html
+    pub fn stats(&self) -> Stats {
+        self.loggers
+            .iter()
+            .map(Logger::stats)
+            .fold(Stats::default(), Stats::merge)
+    }
+
+    /// Initializes the global logger with the composite.
+    ///
+    /// This should be called early in the execution of a Rust program. Any log
+    /// events that occur before initialization will be ignored.
+    ///
+    /// # Errors
+    ///
+    /// This function will fail if it is called more than once, or if another
+    /// library has already initialized a global logger.
+    pub fn try_init(self) -> Result<(), SetLoggerError> {
+        if self.loggers.iter().any(Logger::handles_panics) {
+            panic::install_hook();
+        }
+
+        scope::set_global(self)
+    }
+
+    /// Initializes the global logger with the composite.
+    ///
+    /// This should be called early in the execution of a Rust program. Any log
+    /// events that occur before initialization will be ignored.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if it is called more than once, or if another
+    /// library has already initialized a global logger.
+    pub fn init(self) {
+        self.try_init()
+            .expect("Composite::init should not be called after logger initialized");
+    }
+
+    /// The most verbose level of records any of the loggers needs to see.
+    pub(crate) fn max_level(&self) -> LevelFilter {
+        self.combined(Logger::max_level)
+    }
+
+    fn combined<F>(&self, level: F) -> LevelFilter
+    where
+        F: Fn(&Logger) -> LevelFilter,
+    {
+        let loggers = self.loggers.iter().map(level);
+        let others = self.others.iter().map(|&(_, level)| level);
+
+        loggers.chain(others).max().unwrap_or(LevelFilter::Off)
+    }
+
+    /// Whether any env logger needs to be ticked.
+    pub(crate) fn needs_ticks(&self) -> bool {
+        self.loggers.iter().any(Logger::needs_ticks)
+    }
+
+    /// Let each env logger print anything that's become due.
+    pub(crate) fn tick(&self) {
+        for logger in &self.loggers {
+            logger.tick();
+        }
+    }
+
+    /// Let each env logger print a panic on the current thread.
+    pub(crate) fn panicked(&self, location: Option<&Location>, payload: &(dyn Any + Send)) {
+        for logger in &self.loggers {
+            logger.panicked(location, payload);
+        }
+    }
+}
+
+impl From<Logger> for Composite {
+    fn from(logger: Logger) -> Self {
+        Composite::new().logger(logger)
+    }
+}
+
+impl Log for Composite {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.loggers.iter().any(|logger| logger.enabled(metadata))
+            || self
+                .others
+                .iter()
+                .any(|(log, level)| metadata.level() <= *level && log.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        for logger in &self.loggers {
+            logger.log(record);
+        }
+
+        for (log, level) in &self.others {
+            if record.level() <= *level {
+                log.log(record);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        for logger in &self.loggers {
+            logger.flush();
+        }
+
+        for (log, _) in &self.others {
+            log.flush();
+        }
+    }
+}
+
+impl fmt::Debug for Composite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Composite")
+            .field("loggers", &self.loggers)
+            .field("others", &self.others.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use log::Level;
+
+    use crate::Builder;
+
+    #[derive(Clone, Default)]
+    struct Counter(Arc<AtomicUsize>);
+
+    impl Log for Counter {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, _: &Record) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn logger(level: LevelFilter) -> Logger {
+        Builder::new().filter_level(level).is_test(true).build()
+    }
+
+    fn log(composite: &Composite, level: Level) {
+        composite.log(&Record::builder().level(level).target("app").build());
+    }
+
+    #[test]
+    fn filter_is_the_most_verbose_level() {
+        let composite = Composite::new()
+            .logger(logger(LevelFilter::Warn))
+            .logger(logger(LevelFilter::Debug))
+            .other(Box::new(Counter::default()), LevelFilter::Info);
+
+        assert_eq!(LevelFilter::Debug, composite.filter());
+        assert_eq!(LevelFilter::Off, Composite::new().filter());
+    }
+
+    #[test]
+    fn other_logs_only_see_records_within_their_level() {
+        let counter = Counter::default();
+        let composite = Composite::new()
+            .logger(logger(LevelFilter::Error))
+            .other(Box::new(counter.clone()), LevelFilter::Info);
+
+        log(&composite, Level::Debug);
+        log(&composite, Level::Info);
+        log(&composite, Level::Error);
+
+        assert_eq!(2, counter.0.load(Ordering::SeqCst));
+
+        let metadata = |level| Metadata::builder().level(level).target("app").build();
+        assert!(composite.enabled(&metadata(Level::Info)));
+        assert!(!composite.enabled(&metadata(Level::Debug)));
+    }
+}
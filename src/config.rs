@@ -0,0 +1,746 @@
+//! Loading the logger's configuration from a file.
+//!
+//! A config file covers more than the `RUST_LOG` and `RUST_LOG_STYLE` environment
+//! variables can, like the format and additional outputs. Config files use a
+//! subset of [TOML]: tables, arrays of tables, and keys with string, integer or
+//! boolean values. Other TOML features, like inline tables and arrays, and other
+//! formats like JSON aren't supported.
+//!
+//! ```toml
+//! # The same directives as `RUST_LOG`
+//! filters = "info,my_app::db=debug"
+//! # `auto`, `always` or `never`
+//! write_style = "auto"
+//! # `stdout` or `stderr`, or `file` to write to a file instead
+//! target = "stderr"
+//! # Write records at this level or more severe to `stderr`, and the rest to `stdout`
+//! split_target = "warn"
+//! log_panics = true
+//!
+//! [format]
+//! # `secs`, `millis`, `micros`, `nanos`, or `false`
+//! timestamp = "millis"
+//! level = true
+//! module_path = false
+//! # A number of spaces, or `false`
+//! indent = 4
+//! message_limit = 4096
+//! escape = true
+//!
+//! # Each additional output is its own table
+//! [[output]]
+//! file = "warnings.log"
+//! level = "warn"
+//!
+//! [[output]]
+//! target = "stdout"
+//! write_style = "never"
+//! ```
+//!
+//! Config files are loaded with [`Builder::from_config_file`], or with
+//! [`Builder::from_env`] from the file named by an environment variable, if one is
+//! set with [`Env::config`]. Unknown keys and invalid values are errors, reported
+//! along with their line. Nothing is applied, and no files are opened, unless the
+//! whole config is valid.
+//!
+//! Filters from a config file have the same [`Source`] as `RUST_LOG`, so for the
+//! same module, the one from `RUST_LOG` wins.
+//!
+//! [TOML]: https://toml.io
+//! [`Source`]: ../filter/enum.Source.html
+//! [`Builder::from_config_file`]: ../struct.Builder.html#method.from_config_file
+//! [`Builder::from_env`]: ../struct.Builder.html#method.from_env
+//! [`Env::config`]: ../struct.Env.html#method.config
+
+use std::error;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use log::{Level, LevelFilter};
+
+use crate::filter::Source;
+use crate::fmt::{Target, TimestampPrecision, WriteStyle};
+use crate::{Builder, Output};
+
+/// An error loading a config file.
+#[derive(Debug)]
+pub struct Error {
+    line: Option<usize>,
+    kind: ErrorKind,
+}
+
+#[derive(Debug)]
+enum ErrorKind {
+    Io(io::Error),
+    Invalid(String),
+}
+
+impl Error {
+    fn invalid<M>(line: usize, message: M) -> Self
+    where
+        M: Into<String>,
+    {
+        Error {
+            line: Some(line),
+            kind: ErrorKind::Invalid(message.into()),
+        }
+    }
+
+    /// The line of the config file the error is on, starting from 1.
+    ///
+    /// Errors reading the file aren't on any line.
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+
+        match self.kind {
+            ErrorKind::Io(ref e) => write!(f, "{}", e),
+            ErrorKind::Invalid(ref message) => f.write_str(message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            ErrorKind::Io(ref e) => Some(e),
+            ErrorKind::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error {
+            line: None,
+            kind: ErrorKind::Io(e),
+        }
+    }
+}
+
+/// Read a config file and apply it to a builder.
+pub(crate) fn load_file(builder: &mut Builder, path: &Path) -> Result<(), Error> {
+    load(builder, &fs::read_to_string(path)?)
+}
+
+/// Parse a config and apply it to a builder.
+///
+/// The builder is left as it was if the config is invalid.
+pub(crate) fn load(builder: &mut Builder, config: &str) -> Result<(), Error> {
+    let tables = parse(config)?;
+
+    // Check the whole config before opening any files, so an invalid one
+    // doesn't leave files behind
+    apply(&mut builder.clone(), &tables, Files::Check)?;
+
+    let mut loaded = builder.clone();
+    apply(&mut loaded, &tables, Files::Open)?;
+    *builder = loaded;
+
+    Ok(())
+}
+
+/// What to do with the files named in a config.
+#[derive(Clone, Copy)]
+enum Files {
+    // Only check their names
+    Check,
+    Open,
+}
+
+fn apply(builder: &mut Builder, tables: &[Table], files: Files) -> Result<(), Error> {
+    for table in tables {
+        match table.name {
+            None => apply_root(builder, table, files)?,
+            Some(ref name) if name == "format" && !table.is_array => apply_format(builder, table)?,
+            Some(ref name) if name == "output" && table.is_array => {
+                builder.add_output(output(table, files)?);
+            }
+            Some(ref name) => {
+                return Err(Error::invalid(
+                    table.line,
+                    format!("unknown table `{}`", name),
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_root(builder: &mut Builder, table: &Table, files: Files) -> Result<(), Error> {
+    let mut target = None;
+    let mut file = None;
+
+    for entry in &table.entries {
+        match &*entry.key {
+            "filters" => {
+                builder
+                    .filter
+                    .parse_with_source(entry.string()?, Source::Environment);
+            }
+            "write_style" => {
+                builder.write_style(entry.write_style()?);
+            }
+            "target" => target = Some(entry.target()?),
+            "file" => file = Some(entry),
+            "split_target" => {
+                builder.split_target(entry.level()?);
+            }
+            "log_panics" => {
+                builder.log_panics(entry.boolean()?);
+            }
+            _ => return Err(entry.unknown()),
+        }
+    }
+
+    match (target, file) {
+        (Some(_), Some(file)) => {
+            return Err(Error::invalid(
+                file.line,
+                "`file` can't be used along with `target`",
+            ))
+        }
+        (Some(target), None) => {
+            builder.target(target);
+        }
+        (None, Some(file)) => {
+            builder.pipe(file.open(files)?);
+        }
+        (None, None) => {}
+    }
+
+    Ok(())
+}
+
+fn apply_format(builder: &mut Builder, table: &Table) -> Result<(), Error> {
+    for entry in &table.entries {
+        match &*entry.key {
+            "timestamp" => {
+                builder.format_timestamp(entry.timestamp()?);
+            }
+            "level" => {
+                builder.format_level(entry.boolean()?);
+            }
+            "module_path" => {
+                builder.format_module_path(entry.boolean()?);
+            }
+            "indent" => {
+                builder.format_indent(entry.optional_size()?);
+            }
+            "message_limit" => {
+                builder.format_message_limit(entry.optional_size()?);
+            }
+            "escape" => {
+                builder.format_escape(entry.boolean()?);
+            }
+            _ => return Err(entry.unknown()),
+        }
+    }
+
+    Ok(())
+}
+
+fn output(table: &Table, files: Files) -> Result<Output, Error> {
+    let mut output = None;
+    let mut level = None;
+    let mut write_style = None;
+    let line = table.line;
+
+    for entry in &table.entries {
+        match &*entry.key {
+            "target" | "file" if output.is_some() => {
+                return Err(Error::invalid(
+                    entry.line,
+                    "an output can only have one `target` or `file`",
+                ))
+            }
+            "target" => output = Some(Output::new(entry.target()?)),
+            "file" => output = Some(Output::pipe(entry.open(files)?)),
+            "level" => level = Some(entry.level_filter()?),
+            "write_style" => write_style = Some(entry.write_style()?),
+            _ => return Err(entry.unknown()),
+        }
+    }
+
+    let mut output = output
+        .ok_or_else(|| Error::invalid(line, "an output needs either a `target` or a `file`"))?;
+
+    if let Some(level) = level {
+        output = output.filter_level(level);
+    }
+
+    if let Some(write_style) = write_style {
+        output = output.write_style(write_style);
+    }
+
+    Ok(output)
+}
+
+/// A table of keys, or the keys before the first table.
+#[derive(Debug)]
+struct Table {
+    name: Option<String>,
+    // Whether the table is an element of an array of tables, like `[[output]]`
+    is_array: bool,
+    line: usize,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl Entry {
+    fn unknown(&self) -> Error {
+        Error::invalid(self.line, format!("unknown key `{}`", self.key))
+    }
+
+    fn expected(&self, expected: &str) -> Error {
+        Error::invalid(
+            self.line,
+            format!("invalid value for `{}`, expected {}", self.key, expected),
+        )
+    }
+
+    fn string(&self) -> Result<&str, Error> {
+        match self.value {
+            Value::String(ref s) => Ok(s),
+            _ => Err(self.expected("a string")),
+        }
+    }
+
+    fn boolean(&self) -> Result<bool, Error> {
+        match self.value {
+            Value::Boolean(b) => Ok(b),
+            _ => Err(self.expected("`true` or `false`")),
+        }
+    }
+
+    fn optional_size(&self) -> Result<Option<usize>, Error> {
+        match self.value {
+            Value::Integer(n) if n >= 0 => Ok(Some(n as usize)),
+            Value::Boolean(false) => Ok(None),
+            _ => Err(self.expected("a positive number or `false`")),
+        }
+    }
+
+    fn level(&self) -> Result<Level, Error> {
+        self.string()?
+            .parse()
+            .map_err(|_| self.expected("a level, like `warn`"))
+    }
+
+    fn level_filter(&self) -> Result<LevelFilter, Error> {
+        self.string()?
+            .parse()
+            .map_err(|_| self.expected("a level, like `warn`"))
+    }
+
+    fn write_style(&self) -> Result<WriteStyle, Error> {
+        self.string()
+            .ok()
+            .and_then(parse_write_style)
+            .ok_or_else(|| self.expected("`auto`, `always` or `never`"))
+    }
+
+    fn target(&self) -> Result<Target, Error> {
+        self.string()
+            .ok()
+            .and_then(parse_target)
+            .ok_or_else(|| self.expected("`stdout` or `stderr`"))
+    }
+
+    fn timestamp(&self) -> Result<Option<TimestampPrecision>, Error> {
+        match self.value {
+            Value::Boolean(false) => Some(None),
+            Value::String(ref s) => parse_timestamp(s),
+            _ => None,
+        }
+        .ok_or_else(|| self.expected("`secs`, `millis`, `micros`, `nanos` or `false`"))
+    }
+
+    /// Open the file named by the entry for appending, creating it if needed.
+    ///
+    /// When files are only being checked, nothing is opened and writes are discarded.
+    fn open(&self, files: Files) -> Result<Box<dyn io::Write + Send>, Error> {
+        let path = self.string()?;
+
+        match files {
+            Files::Check => Ok(Box::new(io::sink())),
+            Files::Open => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map(|file| Box::new(file) as Box<dyn io::Write + Send>)
+                .map_err(|e| {
+                    Error::invalid(self.line, format!("failed to open `{}`: {}", path, e))
+                }),
+        }
+    }
+}
+
+/// Parse a write style, like `RUST_LOG_STYLE` but rejecting unknown values.
+pub(crate) fn parse_write_style(s: &str) -> Option<WriteStyle> {
+    match s {
+        "auto" => Some(WriteStyle::Auto),
+        "always" => Some(WriteStyle::Always),
+        "never" => Some(WriteStyle::Never),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_target(s: &str) -> Option<Target> {
+    match s {
+        "stdout" => Some(Target::Stdout),
+        "stderr" => Some(Target::Stderr),
+        _ => None,
+    }
+}
+
+/// Parse a timestamp precision, or `off` for no timestamps.
+pub(crate) fn parse_timestamp(s: &str) -> Option<Option<TimestampPrecision>> {
+    match s {
+        "off" => Some(None),
+        "secs" => Some(Some(TimestampPrecision::Seconds)),
+        "millis" => Some(Some(TimestampPrecision::Millis)),
+        "micros" => Some(Some(TimestampPrecision::Micros)),
+        "nanos" => Some(Some(TimestampPrecision::Nanos)),
+        _ => None,
+    }
+}
+
+/// Parse a config into its tables, in the order they appear.
+fn parse(config: &str) -> Result<Vec<Table>, Error> {
+    let mut tables = vec![Table {
+        name: None,
+        is_array: false,
+        line: 1,
+        entries: Vec::new(),
+    }];
+
+    for (i, line) in config.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            let (name, is_array) = parse_header(line)
+                .ok_or_else(|| Error::invalid(line_number, "invalid table header"))?;
+
+            let is_duplicate = !is_array
+                && tables
+                    .iter()
+                    .any(|table| table.name.as_ref().map(String::as_str) == Some(name));
+            if is_duplicate {
+                return Err(Error::invalid(
+                    line_number,
+                    format!("duplicate table `{}`", name),
+                ));
+            }
+
+            tables.push(Table {
+                name: Some(name.to_owned()),
+                is_array,
+                line: line_number,
+                entries: Vec::new(),
+            });
+            continue;
+        }
+
+        let eq = line
+            .find('=')
+            .ok_or_else(|| Error::invalid(line_number, "expected `key = value`"))?;
+
+        let key = line[..eq].trim();
+        if !is_bare_key(key) {
+            return Err(Error::invalid(
+                line_number,
+                format!("invalid key `{}`", key),
+            ));
+        }
+
+        let value = parse_value(line[eq + 1..].trim())
+            .map_err(|message| Error::invalid(line_number, message))?;
+
+        let table = tables.last_mut().expect("missing root table");
+        if table.entries.iter().any(|entry| entry.key == key) {
+            return Err(Error::invalid(
+                line_number,
+                format!("duplicate key `{}`", key),
+            ));
+        }
+
+        table.entries.push(Entry {
+            key: key.to_owned(),
+            value,
+            line: line_number,
+        });
+    }
+
+    Ok(tables)
+}
+
+/// Parse a table header, like `[format]` or `[[output]]`, with an optional comment.
+fn parse_header(line: &str) -> Option<(&str, bool)> {
+    let (line, is_array) = if line.starts_with("[[") {
+        (&line[2..], true)
+    } else {
+        (&line[1..], false)
+    };
+
+    let close = if is_array { "]]" } else { "]" };
+    let end = line.find(close)?;
+
+    let name = line[..end].trim();
+    let rest = line[end + close.len()..].trim();
+
+    if is_bare_key(name) && (rest.is_empty() || rest.starts_with('#')) {
+        Some((name, is_array))
+    } else {
+        None
+    }
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parse a value, with an optional comment after it.
+fn parse_value(s: &str) -> Result<Value, String> {
+    let (value, rest) = if s.starts_with('"') {
+        parse_basic_string(&s[1..])?
+    } else if s.starts_with('\'') {
+        let rest = &s[1..];
+        let end = rest.find('\'').ok_or("unterminated string")?;
+        (Value::String(rest[..end].to_owned()), &rest[end + 1..])
+    } else {
+        let end = s.find('#').unwrap_or(s.len());
+        let token = s[..end].trim();
+
+        let value = match token {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            "" => return Err("missing value".to_owned()),
+            _ => Value::Integer(
+                token
+                    .replace('_', "")
+                    .parse()
+                    .map_err(|_| format!("invalid value `{}`", token))?,
+            ),
+        };
+
+        (value, &s[end..])
+    };
+
+    let rest = rest.trim();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(value)
+    } else {
+        Err(format!("unexpected `{}` after value", rest))
+    }
+}
+
+/// Parse a double-quoted string, starting after the opening quote.
+fn parse_basic_string(s: &str) -> Result<(Value, &str), String> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((Value::String(value), &s[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, '"')) => value.push('"'),
+                Some((_, '\\')) => value.push('\\'),
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c)) => return Err(format!("invalid escape `\\{}`", c)),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+
+    Err("unterminated string".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(config: &str) -> String {
+        load(&mut Builder::new(), config)
+            .expect_err("expected an error")
+            .to_string()
+    }
+
+    #[test]
+    fn parse_tables_and_values() {
+        let tables = parse(
+            "
+            # a comment
+            filters = \"info,app=debug\" # trailing comment
+            log_panics = true
+
+            [format]
+            indent = 4
+            timestamp = 'millis'
+
+            [[output]]
+            file = \"a \\\"quoted\\\" path\"
+
+            [[output]]
+            target = \"stdout\"
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(4, tables.len());
+
+        assert_eq!(None, tables[0].name);
+        assert_eq!("filters", tables[0].entries[0].key);
+        assert_eq!(
+            Value::String("info,app=debug".to_owned()),
+            tables[0].entries[0].value
+        );
+        assert_eq!(3, tables[0].entries[0].line);
+        assert_eq!(Value::Boolean(true), tables[0].entries[1].value);
+
+        assert_eq!(Some("format"), tables[1].name.as_ref().map(String::as_str));
+        assert!(!tables[1].is_array);
+        assert_eq!(Value::Integer(4), tables[1].entries[0].value);
+        assert_eq!(
+            Value::String("millis".to_owned()),
+            tables[1].entries[1].value
+        );
+
+        assert_eq!(Some("output"), tables[2].name.as_ref().map(String::as_str));
+        assert!(tables[2].is_array);
+        assert_eq!(
+            Value::String("a \"quoted\" path".to_owned()),
+            tables[2].entries[0].value
+        );
+        assert_eq!(13, tables[3].line);
+    }
+
+    #[test]
+    fn load_applies_settings() {
+        let mut builder = Builder::new();
+
+        load(
+            &mut builder,
+            "
+            filters = \"warn,app=debug\"
+            split_target = \"warn\"
+
+            [format]
+            timestamp = false
+            module_path = false
+            indent = false
+
+            [[output]]
+            target = \"stdout\"
+            level = \"error\"
+            write_style = \"never\"
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(1, builder.outputs.len());
+        assert_eq!(LevelFilter::Error, builder.outputs[0].level);
+        assert!(builder.format.format_timestamp.is_none());
+        assert!(!builder.format.format_module_path);
+        assert_eq!(None, builder.format.format_indent);
+
+        assert_eq!(LevelFilter::Debug, builder.build().filter());
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        assert_eq!(
+            "line 3: unknown key `colour`",
+            error("filters = \"info\"\n\ncolour = true")
+        );
+        assert_eq!(
+            "line 2: invalid value for `write_style`, expected `auto`, `always` or `never`",
+            error("\nwrite_style = \"sometimes\"")
+        );
+        assert_eq!(
+            "line 2: invalid value for `level`, expected `true` or `false`",
+            error("[format]\nlevel = \"yes\"")
+        );
+        assert_eq!("line 1: unterminated string", error("filters = \"info"));
+        assert_eq!("line 1: unknown table `outputs`", error("[[outputs]]"));
+        assert_eq!(
+            "line 2: duplicate key `filters`",
+            error("filters = \"a\"\nfilters = \"b\"")
+        );
+        assert_eq!("line 1: expected `key = value`", error("filters"));
+        assert_eq!(
+            "line 1: an output needs either a `target` or a `file`",
+            error("[[output]]\nlevel = \"warn\"")
+        );
+        assert_eq!(
+            "line 3: `file` can't be used along with `target`",
+            error("target = \"stdout\"\n\nfile = \"app.log\"")
+        );
+    }
+
+    #[test]
+    fn invalid_config_opens_no_files() {
+        let path =
+            std::env::temp_dir().join(format!("env_logger-config-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut builder = Builder::new();
+        let err = load(
+            &mut builder,
+            &format!(
+                "[[output]]\nfile = '{}'\n\n[format]\ncolour = true",
+                path.display()
+            ),
+        )
+        .expect_err("expected an error");
+
+        assert_eq!(Some(5), err.line());
+        assert!(!path.exists());
+        assert!(builder.outputs.is_empty());
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let err = load_file(
+            &mut Builder::new(),
+            Path::new("/this/config/does/not/exist.toml"),
+        )
+        .expect_err("expected an error");
+
+        assert_eq!(None, err.line());
+        assert!(error::Error::source(&err).is_some());
+    }
+}
@@ -0,0 +1,283 @@
+/*
+This internal module collapses consecutive duplicate records.
+
+The last record that was printed is kept, and records with the same level,
+target and message that follow it are counted instead of printed. The count is
+printed as a single line once a different record arrives, the timeout elapses,
+or the logger is flushed. Installed loggers are ticked so a count whose timeout
+has elapsed is printed even if nothing else is logged.
+*/
+
+use std::fmt::{self, Write};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use log::{Level, Record};
+
+pub(crate) struct Deduplicator {
+    timeout: Duration,
+    last: Mutex<Option<Last>>,
+}
+
+/// The last record that was printed.
+struct Last {
+    level: Level,
+    target: String,
+    message: String,
+    module_path: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    repeated: u64,
+    // When the first repeat that hasn't been reported yet was seen
+    since: Instant,
+}
+
+/// A number of times a record was repeated without being printed.
+pub(crate) struct Repeated {
+    level: Level,
+    target: String,
+    module_path: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    times: u64,
+}
+
+impl Deduplicator {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Deduplicator {
+            timeout,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Whether a record should be printed, or is a repeat of the last one.
+    ///
+    /// If the repeats of the last record need reporting first, they're returned
+    /// in `repeated`.
+    pub(crate) fn check(&self, record: &Record, repeated: &mut Option<Repeated>) -> bool {
+        let now = Instant::now();
+        let mut last = self.lock();
+
+        if let Some(ref mut last) = *last {
+            if last.is_repeated_by(record) {
+                if last.repeated == 0 {
+                    last.since = now;
+                } else if now.duration_since(last.since) >= self.timeout {
+                    *repeated = last.take_repeated();
+                    last.since = now;
+                }
+
+                last.repeated += 1;
+                return false;
+            }
+
+            *repeated = last.take_repeated();
+        }
+
+        *last = Some(Last {
+            level: record.level(),
+            target: record.target().to_owned(),
+            message: record.args().to_string(),
+            module_path: record.module_path().map(Into::into),
+            file: record.file().map(Into::into),
+            line: record.line(),
+            repeated: 0,
+            since: now,
+        });
+
+        true
+    }
+
+    /// Take the repeats of the last record if they've been waiting for the timeout.
+    pub(crate) fn tick(&self) -> Option<Repeated> {
+        match *self.lock() {
+            Some(ref mut last) if last.repeated > 0 && last.since.elapsed() >= self.timeout => {
+                last.take_repeated()
+            }
+            _ => None,
+        }
+    }
+
+    /// Take any repeats of the last record that haven't been reported yet.
+    pub(crate) fn flush(&self) -> Option<Repeated> {
+        self.lock().as_mut().and_then(Last::take_repeated)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Last>> {
+        // A panic while holding the lock can't leave the last record in an invalid state
+        self.last.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Last {
+    fn is_repeated_by(&self, record: &Record) -> bool {
+        if self.level != record.level() || self.target != record.target() {
+            return false;
+        }
+
+        // Compare the message as it's formatted, without allocating a new string
+        let mut remaining = &*self.message;
+        let matches = write!(Compare(&mut remaining), "{}", record.args()).is_ok();
+
+        matches && remaining.is_empty()
+    }
+
+    fn take_repeated(&mut self) -> Option<Repeated> {
+        if self.repeated == 0 {
+            return None;
+        }
+
+        let times = self.repeated;
+        self.repeated = 0;
+
+        Some(Repeated {
+            level: self.level,
+            target: self.target.clone(),
+            module_path: self.module_path.clone(),
+            file: self.file.clone(),
+            line: self.line,
+            times,
+        })
+    }
+}
+
+/// Checks that the text written to it is a prefix of a string, consuming it as it goes.
+struct Compare<'a, 'b>(&'a mut &'b str);
+
+impl<'a, 'b> fmt::Write for Compare<'a, 'b> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.0.starts_with(s) {
+            *self.0 = &self.0[s.len()..];
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
+
+impl Repeated {
+    /// Call `f` with a record reporting the repeats.
+    ///
+    /// The record has the same metadata as the repeated record, so it goes to the
+    /// same outputs and can be formatted the same way.
+    pub(crate) fn with_record<F>(&self, f: F)
+    where
+        F: FnOnce(&Record),
+    {
+        f(&Record::builder()
+            .level(self.level)
+            .target(&self.target)
+            .module_path(self.module_path.as_ref().map(String::as_str))
+            .file(self.file.as_ref().map(String::as_str))
+            .line(self.line)
+            .args(format_args!("last message repeated {} times", self.times))
+            .build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(
+        dedup: &Deduplicator,
+        level: Level,
+        message: &str,
+        repeated: &mut Option<Repeated>,
+    ) -> bool {
+        dedup.check(
+            &Record::builder()
+                .level(level)
+                .target("app")
+                .args(format_args!("{}", message))
+                .build(),
+            repeated,
+        )
+    }
+
+    fn message(repeated: Option<Repeated>) -> Option<String> {
+        repeated.map(|repeated| {
+            let mut message = None;
+            repeated.with_record(|record| message = Some(record.args().to_string()));
+            message.unwrap()
+        })
+    }
+
+    #[test]
+    fn collapses_consecutive_duplicates() {
+        let dedup = Deduplicator::new(Duration::from_secs(60));
+        let mut repeated = None;
+
+        assert!(check(&dedup, Level::Info, "a", &mut repeated));
+        assert!(!check(&dedup, Level::Info, "a", &mut repeated));
+        assert!(!check(&dedup, Level::Info, "a", &mut repeated));
+        assert!(repeated.is_none());
+
+        assert!(check(&dedup, Level::Info, "b", &mut repeated));
+        assert_eq!(
+            Some("last message repeated 2 times".to_owned()),
+            message(repeated.take())
+        );
+
+        assert!(check(&dedup, Level::Info, "a", &mut repeated));
+        assert!(repeated.is_none());
+    }
+
+    #[test]
+    fn different_levels_are_not_duplicates() {
+        let dedup = Deduplicator::new(Duration::from_secs(60));
+        let mut repeated = None;
+
+        assert!(check(&dedup, Level::Info, "a", &mut repeated));
+        assert!(check(&dedup, Level::Warn, "a", &mut repeated));
+        assert!(check(&dedup, Level::Warn, "ab", &mut repeated));
+        assert!(check(&dedup, Level::Warn, "a", &mut repeated));
+        assert!(repeated.is_none());
+    }
+
+    #[test]
+    fn reports_repeats_after_timeout() {
+        let dedup = Deduplicator::new(Duration::from_secs(1));
+        let mut repeated = None;
+
+        assert!(check(&dedup, Level::Info, "a", &mut repeated));
+        assert!(!check(&dedup, Level::Info, "a", &mut repeated));
+
+        if let Some(ref mut last) = *dedup.lock() {
+            last.since -= Duration::from_secs(1);
+        }
+
+        assert!(!check(&dedup, Level::Info, "a", &mut repeated));
+        assert_eq!(
+            Some("last message repeated 1 times".to_owned()),
+            message(repeated.take())
+        );
+
+        assert_eq!(
+            Some("last message repeated 1 times".to_owned()),
+            message(dedup.flush())
+        );
+        assert!(dedup.flush().is_none());
+    }
+
+    #[test]
+    fn tick_reports_repeats_after_timeout() {
+        let dedup = Deduplicator::new(Duration::from_secs(1));
+        let mut repeated = None;
+
+        assert!(check(&dedup, Level::Info, "a", &mut repeated));
+        assert!(!check(&dedup, Level::Info, "a", &mut repeated));
+        assert!(dedup.tick().is_none());
+
+        if let Some(ref mut last) = *dedup.lock() {
+            last.since -= Duration::from_secs(1);
+        }
+
+        assert_eq!(
+            Some("last message repeated 1 times".to_owned()),
+            message(dedup.tick())
+        );
+        assert!(dedup.tick().is_none());
+        assert!(dedup.flush().is_none());
+    }
+}
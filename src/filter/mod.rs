@@ -60,9 +60,15 @@
 //! [`Filter::matches`]: struct.Filter.html#method.matches
 
 use log::{Level, LevelFilter, Metadata, Record};
+use std::cell::Cell;
+use std::cmp;
+use std::collections::hash_map::RandomState;
 use std::env;
 use std::fmt;
-use std::mem;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::RateLimit;
 
 #[cfg(feature = "regex")]
 #[path = "regex.rs"]
@@ -113,19 +119,120 @@ pub struct Filter {
 /// ```
 ///
 /// [`Filter`]: struct.Filter.html
+#[derive(Clone)]
 pub struct Builder {
     directives: Vec<Directive>,
     filter: Option<inner::Filter>,
-    built: bool,
+    filter_source: Source,
+    // The fallback level set by `verbosity`
+    verbosity: Option<LevelFilter>,
 }
 
-#[derive(Debug)]
-struct Directive {
+/// A directive in a filter.
+///
+/// Directives set the most verbose level to log for a module, or for every module.
+/// The directives in effect can be inspected with [`Filter::directives`].
+///
+/// [`Filter::directives`]: struct.Filter.html#method.directives
+#[derive(Clone, Debug)]
+pub struct Directive {
     name: Option<String>,
     level: LevelFilter,
+    rate_limit: Option<RateLimit>,
+    // The fraction of records below `warn` to keep
+    sample: Option<f64>,
+    source: Source,
+}
+
+/// Whether a record matches a filter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Verdict {
+    Matched,
+    // Filtered out by the directives or sampling. Records are only recordable
+    // if they're filtered out by a level no stricter than the default one, not
+    // by sampling
+    Filtered { recordable: bool },
+    // Matched a directive, but not the message filter
+    Rejected,
+}
+
+/// Where a directive came from.
+///
+/// When several directives name the same module, the one from the source that
+/// comes last in this list wins. Between directives from the same source, the
+/// one added last wins. The order the sources are added in doesn't matter.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Source {
+    /// A default, like the `error` level used when there are no other directives,
+    /// or the default value of an [`Env`] variable.
+    ///
+    /// [`Env`]: ../struct.Env.html
+    Default,
+    /// Directives added in code, like with [`Builder::filter`].
+    ///
+    /// [`Builder::filter`]: struct.Builder.html#method.filter
+    Code,
+    /// Directives read from the environment, like `RUST_LOG`, or a config file.
+    Environment,
+    /// Overrides set while the program is running, like the verbosity from
+    /// command line flags.
+    Override,
+}
+
+impl Directive {
+    /// The module the directive applies to, or `None` if it applies to every module.
+    pub fn module(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+
+    /// The most verbose level to log.
+    pub fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    /// The rate limit set by the directive, if any.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit
+    }
+
+    /// The fraction of records below `warn` the directive keeps, if it samples them.
+    pub fn sample_rate(&self) -> Option<f64> {
+        self.sample
+    }
+
+    /// Where the directive came from.
+    pub fn source(&self) -> Source {
+        self.source
+    }
 }
 
 impl Filter {
+    /// Returns the directives in effect, after merging the directives from each source.
+    ///
+    /// Each module has at most one directive. They're ordered from the least specific
+    /// module to the most specific.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use log::LevelFilter;
+    /// use env_logger::filter::{Builder, Source};
+    ///
+    /// let mut builder = Builder::new();
+    /// builder
+    ///     .parse_with_source("info,my_app=debug", Source::Environment)
+    ///     .filter(Some("my_app"), LevelFilter::Warn);
+    ///
+    /// let filter = builder.build();
+    ///
+    /// let my_app = &filter.directives()[1];
+    /// assert_eq!(my_app.level(), LevelFilter::Debug);
+    /// assert_eq!(my_app.source(), Source::Environment);
+    /// ```
+    pub fn directives(&self) -> &[Directive] {
+        &self.directives
+    }
+
     /// Returns the maximum `LevelFilter` that this filter instance is
     /// configured to output.
     ///
@@ -157,17 +264,35 @@ impl Filter {
 
     /// Checks if this record matches the configured filter.
     pub fn matches(&self, record: &Record) -> bool {
-        if !self.enabled(record.metadata()) {
-            return false;
+        self.check(record) == Verdict::Matched
+    }
+
+    /// Checks a record, telling apart why it doesn't match.
+    pub(crate) fn check(&self, record: &Record) -> Verdict {
+        let level = record.level();
+
+        let directive = match directive(&self.directives, record.target()) {
+            Some(directive) => directive,
+            None => return Verdict::Filtered { recordable: false },
+        };
+
+        if level > directive.level {
+            return Verdict::Filtered {
+                recordable: self.is_recordable(directive),
+            };
+        }
+
+        if !directive.is_sampled(level) {
+            return Verdict::Filtered { recordable: false };
         }
 
         if let Some(filter) = self.filter.as_ref() {
             if !filter.is_match(&*record.args().to_string()) {
-                return false;
+                return Verdict::Rejected;
             }
         }
 
-        true
+        Verdict::Matched
     }
 
     /// Determines if a log message with the specified metadata would be logged.
@@ -177,6 +302,44 @@ impl Filter {
 
         enabled(&self.directives, level, target)
     }
+
+    /// Whether records with the metadata may be kept by a flight recorder when
+    /// they're filtered out.
+    ///
+    /// Records aren't recordable if they're turned off, or filtered out more
+    /// strictly than the default level, on purpose.
+    pub(crate) fn recordable(&self, metadata: &Metadata) -> bool {
+        match directive(&self.directives, metadata.target()) {
+            Some(directive) => self.is_recordable(directive),
+            None => false,
+        }
+    }
+
+    fn is_recordable(&self, directive: &Directive) -> bool {
+        let default = self
+            .directives
+            .iter()
+            .find(|d| d.name.is_none())
+            .map(|d| d.level)
+            .unwrap_or(LevelFilter::Off);
+
+        directive.level != LevelFilter::Off && directive.level >= default
+    }
+
+    /// Returns the rate limit set by the directive matching the metadata, if any.
+    ///
+    /// Rate limits are set by adding `~N` to a directive.
+    /// See [`RateLimit`] for more details.
+    ///
+    /// [`RateLimit`]: ../struct.RateLimit.html
+    pub fn rate_limit(&self, metadata: &Metadata) -> Option<RateLimit> {
+        directive(&self.directives, metadata.target()).and_then(|d| d.rate_limit)
+    }
+
+    /// Whether any directive sets a rate limit.
+    pub(crate) fn has_rate_limits(&self) -> bool {
+        self.directives.iter().any(|d| d.rate_limit.is_some())
+    }
 }
 
 impl Builder {
@@ -185,7 +348,8 @@ impl Builder {
         Builder {
             directives: Vec::new(),
             filter: None,
-            built: false,
+            filter_source: Source::Default,
+            verbosity: None,
         }
     }
 
@@ -194,7 +358,7 @@ impl Builder {
         let mut builder = Builder::new();
 
         if let Ok(s) = env::var(env) {
-            builder.parse(&s);
+            builder.parse_with_source(&s, Source::Environment);
         }
 
         builder
@@ -218,41 +382,173 @@ impl Builder {
         self.directives.push(Directive {
             name: module.map(|s| s.to_string()),
             level,
+            rate_limit: None,
+            sample: None,
+            source: Source::Code,
         });
         self
     }
 
+    /// Adds a directive to the filter from the given source.
+    ///
+    /// See [`Source`] for how directives from different sources are merged.
+    ///
+    /// [`Source`]: enum.Source.html
+    pub fn filter_with_source(
+        &mut self,
+        module: Option<&str>,
+        level: LevelFilter,
+        source: Source,
+    ) -> &mut Self {
+        self.directives.push(Directive {
+            name: module.map(|s| s.to_string()),
+            level,
+            rate_limit: None,
+            sample: None,
+            source,
+        });
+        self
+    }
+
+    /// Adds a directive to the filter that only keeps a sample of its records.
+    ///
+    /// This is like [`filter`], but only a random fraction of the records below
+    /// `warn` are kept, given by `rate` between `0.0` and `1.0`. Warnings and errors
+    /// are always kept. A `rate` outside that range is clamped to it, and `NaN`
+    /// keeps no records.
+    ///
+    /// [`filter`]: #method.filter
+    pub fn filter_sampled(
+        &mut self,
+        module: Option<&str>,
+        level: LevelFilter,
+        rate: f64,
+    ) -> &mut Self {
+        self.directives.push(Directive {
+            name: module.map(|s| s.to_string()),
+            level,
+            rate_limit: None,
+            sample: Some(clamp_rate(rate)),
+            source: Source::Code,
+        });
+        self
+    }
+
+    /// Sets the fallback level from a verbosity, like the number of `-v` flags
+    /// given to a command line program.
+    ///
+    /// The fallback level is `default` made more verbose by `delta` levels, or
+    /// less verbose if `delta` is negative, and stays between `off` and `trace`.
+    /// It's merged with the other directives when the filter is built:
+    ///
+    /// - It replaces the level of a directive without a module added in code,
+    ///   whether it was added before or after calling this method. Rate limits
+    ///   and sampling set on that directive are kept.
+    /// - A directive without a module read from the environment, like
+    ///   `RUST_LOG=debug`, or set as an override wins over it, so the user's
+    ///   own choice of level isn't lost.
+    /// - Directives for a module are kept, so they still win for that module.
+    /// - Calling this method again replaces the previous fallback level.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use log::LevelFilter;
+    /// use env_logger::filter::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// // Like `-vv` on top of `info,my_app::db=trace` set in code
+    /// builder.parse("info,my_app::db=trace").verbosity(LevelFilter::Warn, 2);
+    ///
+    /// let filter = builder.build();
+    /// assert_eq!(filter.filter(), LevelFilter::Trace);
+    /// ```
+    pub fn verbosity(&mut self, default: LevelFilter, delta: i32) -> &mut Self {
+        self.verbosity = Some(shift_level(default, delta));
+        self
+    }
+
     /// Parses the directives string.
     ///
     /// See the [Enabling Logging] section for more details.
     ///
     /// [Enabling Logging]: ../index.html#enabling-logging
     pub fn parse(&mut self, filters: &str) -> &mut Self {
+        self.parse_with_source(filters, Source::Code)
+    }
+
+    /// Parses the directives string from the given source.
+    ///
+    /// The regex filter, if there is one, replaces any regex filter from the same
+    /// or an earlier source. See [`Source`] for how directives are merged.
+    ///
+    /// [`Source`]: enum.Source.html
+    pub fn parse_with_source(&mut self, filters: &str, source: Source) -> &mut Self {
         let (directives, filter) = parse_spec(filters);
 
-        self.filter = filter;
+        if filter.is_some() && (self.filter.is_none() || self.filter_source <= source) {
+            self.filter = filter;
+            self.filter_source = source;
+        }
 
-        for directive in directives {
+        for mut directive in directives {
+            directive.source = source;
             self.directives.push(directive);
         }
         self
     }
 
     /// Build a log filter.
-    pub fn build(&mut self) -> Filter {
-        assert!(!self.built, "attempt to re-use consumed builder");
-        self.built = true;
+    ///
+    /// The builder isn't consumed, so it can be used to build any number of filters.
+    pub fn build(&self) -> Filter {
+        // Directives from later sources replace earlier ones for the same module,
+        // and within a source the last directive wins. The sort is stable, so
+        // directives from the same source stay in the order they were added.
+        let mut sorted = self.directives.clone();
+        sorted.sort_by_key(|d| d.source);
 
-        if self.directives.is_empty() {
+        let mut directives: Vec<Directive> = Vec::new();
+        for directive in sorted {
+            match directives.iter_mut().find(|d| d.name == directive.name) {
+                Some(existing) => *existing = directive,
+                None => directives.push(directive),
+            }
+        }
+
+        if let Some(level) = self.verbosity {
+            // Rate limits and sampling on the fallback directive are kept
+            match directives.iter_mut().find(|d| d.name.is_none()) {
+                // A level the user set in the environment is kept
+                Some(ref fallback) if fallback.source >= Source::Environment => {}
+                Some(fallback) => {
+                    fallback.level = level;
+                    fallback.source = Source::Override;
+                }
+                None => directives.push(Directive {
+                    name: None,
+                    level,
+                    rate_limit: None,
+                    sample: None,
+                    source: Source::Override,
+                }),
+            }
+        }
+
+        if directives.is_empty() {
             // Adds the default filter if none exist
-            self.directives.push(Directive {
+            directives.push(Directive {
                 name: None,
                 level: LevelFilter::Error,
+                rate_limit: None,
+                sample: None,
+                source: Source::Default,
             });
         } else {
             // Sort the directives by length of their name, this allows a
             // little more efficient lookup at runtime.
-            self.directives.sort_by(|a, b| {
+            directives.sort_by(|a, b| {
                 let alen = a.name.as_ref().map(|a| a.len()).unwrap_or(0);
                 let blen = b.name.as_ref().map(|b| b.len()).unwrap_or(0);
                 alen.cmp(&blen)
@@ -260,8 +556,8 @@ impl Builder {
         }
 
         Filter {
-            directives: mem::replace(&mut self.directives, Vec::new()),
-            filter: mem::replace(&mut self.filter, None),
+            directives,
+            filter: self.filter.clone(),
         }
     }
 }
@@ -283,14 +579,11 @@ impl fmt::Debug for Filter {
 
 impl fmt::Debug for Builder {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.built {
-            f.debug_struct("Filter").field("built", &true).finish()
-        } else {
-            f.debug_struct("Filter")
-                .field("filter", &self.filter)
-                .field("directives", &self.directives)
-                .finish()
-        }
+        f.debug_struct("Filter")
+            .field("filter", &self.filter)
+            .field("directives", &self.directives)
+            .field("verbosity", &self.verbosity)
+            .finish()
     }
 }
 
@@ -315,6 +608,7 @@ fn parse_spec(spec: &str) -> (Vec<Directive>, Option<inner::Filter>) {
             if s.len() == 0 {
                 continue;
             }
+            let (s, rate_limit, sample) = parse_suffixes(s);
             let mut parts = s.split('=');
             let (log_level, name) =
                 match (parts.next(), parts.next().map(|s| s.trim()), parts.next()) {
@@ -350,6 +644,9 @@ fn parse_spec(spec: &str) -> (Vec<Directive>, Option<inner::Filter>) {
             dirs.push(Directive {
                 name: name.map(|s| s.to_string()),
                 level: log_level,
+                rate_limit,
+                sample,
+                source: Source::Code,
             });
         }
     });
@@ -365,23 +662,164 @@ fn parse_spec(spec: &str) -> (Vec<Directive>, Option<inner::Filter>) {
     return (dirs, filter);
 }
 
+/// Make a level more verbose by `delta` levels, or less verbose if it's negative.
+fn shift_level(level: LevelFilter, delta: i32) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+
+    let i = cmp::min(
+        cmp::max(level as i64 + i64::from(delta), 0),
+        LEVELS.len() as i64 - 1,
+    );
+    LEVELS[i as usize]
+}
+
+/// Split the rate limit (e.g: "~100") and sample rate (e.g: "@0.01") off the end of a directive.
+///
+/// Invalid values are ignored with a warning. Sample rates outside `0` to `1`
+/// are clamped, like with `Builder::filter_sampled`.
+fn parse_suffixes(spec: &str) -> (&str, Option<RateLimit>, Option<f64>) {
+    let mut rest = spec;
+    let mut rate_limit = None;
+    let mut sample = None;
+
+    while let Some(i) = rest.rfind(&['~', '@'][..]) {
+        let value = &rest[i + 1..];
+
+        if rest[i..].starts_with('~') {
+            match parse_rate_limit(value) {
+                Some(limit) => rate_limit = Some(limit),
+                None => eprintln!(
+                    "warning: invalid rate limit '{}', \
+                     ignoring it",
+                    value
+                ),
+            }
+        } else {
+            match value.trim().parse() {
+                Ok(rate) => sample = Some(clamp_rate(rate)),
+                Err(_) => eprintln!(
+                    "warning: invalid sample rate '{}', \
+                     ignoring it",
+                    value
+                ),
+            }
+        }
+
+        rest = &rest[..i];
+    }
+
+    (rest, rate_limit, sample)
+}
+
+/// Keep a sample rate between `0.0` and `1.0`, treating `NaN` as keeping no records.
+fn clamp_rate(rate: f64) -> f64 {
+    if rate.is_nan() {
+        0.0
+    } else {
+        rate.max(0.0).min(1.0)
+    }
+}
+
+/// Parse a rate limit (e.g: "100" or "100:10s") into a number of records per period.
+///
+/// The period defaults to a second, and a bare number is a number of seconds.
+fn parse_rate_limit(spec: &str) -> Option<RateLimit> {
+    let mut parts = spec.splitn(2, ':');
+    let records = parts.next()?.trim().parse().ok()?;
+
+    let period = match parts.next().map(|s| s.trim()) {
+        None => Duration::from_secs(1),
+        Some(period) => {
+            let split = period
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(period.len());
+            let n: u64 = period[..split].parse().ok()?;
+
+            match &period[split..] {
+                "ms" => Duration::from_millis(n),
+                "" | "s" => Duration::from_secs(n),
+                "m" => Duration::from_secs(n.checked_mul(60)?),
+                "h" => Duration::from_secs(n.checked_mul(60 * 60)?),
+                _ => return None,
+            }
+        }
+    };
+
+    if period == Duration::from_secs(0) {
+        return None;
+    }
+
+    Some(RateLimit::new(records, period))
+}
+
 // Check whether a level and target are enabled by the set of directives.
 fn enabled(directives: &[Directive], level: Level, target: &str) -> bool {
-    // Search for the longest match, the vector is assumed to be pre-sorted.
-    for directive in directives.iter().rev() {
-        match directive.name {
-            Some(ref name) if !target.starts_with(&**name) => {}
-            Some(..) | None => return level <= directive.level,
+    match directive(directives, target) {
+        Some(directive) => level <= directive.level,
+        None => false,
+    }
+}
+
+impl Directive {
+    // Check whether a record at the given level is kept by the directive's sample rate.
+    fn is_sampled(&self, level: Level) -> bool {
+        match self.sample {
+            Some(rate) if level > Level::Warn => random() < rate,
+            _ => true,
         }
     }
-    false
+}
+
+// A cheap random number in `[0, 1)` from a thread-local xorshift generator.
+fn random() -> f64 {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(0);
+    }
+
+    STATE
+        .try_with(|state| {
+            let mut x = state.get();
+            if x == 0 {
+                // `RandomState` is randomly seeded for each thread
+                x = RandomState::new().build_hasher().finish() | 1;
+            }
+
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            state.set(x);
+
+            (x >> 11) as f64 / (1u64 << 53) as f64
+        })
+        .unwrap_or(0.0)
+}
+
+// Find the directive that applies to a target.
+fn directive<'a>(directives: &'a [Directive], target: &str) -> Option<&'a Directive> {
+    // Search for the longest match, the vector is assumed to be pre-sorted.
+    directives
+        .iter()
+        .rev()
+        .find(|directive| match directive.name {
+            Some(ref name) => target.starts_with(&**name),
+            None => true,
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use log::{Level, LevelFilter};
+    use std::time::Duration;
 
-    use super::{enabled, parse_spec, Builder, Directive, Filter};
+    use super::{enabled, parse_spec, Builder, Directive, Filter, Source, Verdict};
+    use crate::RateLimit;
 
     fn make_logger_filter(dirs: Vec<Directive>) -> Filter {
         let mut logger = Builder::new().build();
@@ -389,6 +827,22 @@ mod tests {
         logger
     }
 
+    #[test]
+    fn builder_can_build_more_than_once() {
+        let mut builder = Builder::new();
+        builder
+            .parse("info,crate1=debug")
+            .verbosity(LevelFilter::Warn, 1);
+
+        let first = builder.build();
+        let second = builder.build();
+
+        assert_eq!(first.directives().len(), second.directives().len());
+        assert!(enabled(&second.directives, Level::Debug, "crate1"));
+        assert!(enabled(&second.directives, Level::Info, "crate2"));
+        assert!(!enabled(&second.directives, Level::Debug, "crate2"));
+    }
+
     #[test]
     fn filter_info() {
         let logger = Builder::new().filter(None, LevelFilter::Info).build();
@@ -420,10 +874,16 @@ mod tests {
             Directive {
                 name: Some("crate2".to_string()),
                 level: LevelFilter::Info,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
             Directive {
                 name: Some("crate1::mod1".to_string()),
                 level: LevelFilter::Warn,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
         ]);
         assert!(enabled(&logger.directives, Level::Warn, "crate1::mod1"));
@@ -438,10 +898,16 @@ mod tests {
             Directive {
                 name: Some("crate2".to_string()),
                 level: LevelFilter::Info,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
             Directive {
                 name: Some("crate1::mod1".to_string()),
                 level: LevelFilter::Warn,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
         ]);
         assert!(!enabled(&logger.directives, Level::Warn, "crate3"));
@@ -453,10 +919,16 @@ mod tests {
             Directive {
                 name: Some("crate2".to_string()),
                 level: LevelFilter::Info,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
             Directive {
                 name: Some("crate1::mod1".to_string()),
                 level: LevelFilter::Warn,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
         ]);
         assert!(enabled(&logger.directives, Level::Info, "crate2::mod1"));
@@ -468,14 +940,23 @@ mod tests {
             Directive {
                 name: Some("crate2".to_string()),
                 level: LevelFilter::Info,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
             Directive {
                 name: Some("crate2::mod".to_string()),
                 level: LevelFilter::Debug,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
             Directive {
                 name: Some("crate1::mod1".to_string()),
                 level: LevelFilter::Warn,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
         ]);
         assert!(enabled(&logger.directives, Level::Debug, "crate2::mod1"));
@@ -488,10 +969,16 @@ mod tests {
             Directive {
                 name: None,
                 level: LevelFilter::Info,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
             Directive {
                 name: Some("crate1::mod1".to_string()),
                 level: LevelFilter::Warn,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
         ]);
         assert!(enabled(&logger.directives, Level::Warn, "crate1::mod1"));
@@ -504,10 +991,16 @@ mod tests {
             Directive {
                 name: None,
                 level: LevelFilter::Info,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
             Directive {
                 name: Some("crate1::mod1".to_string()),
                 level: LevelFilter::Off,
+                rate_limit: None,
+                sample: None,
+                source: Source::Code,
             },
         ]);
         assert!(!enabled(&logger.directives, Level::Error, "crate1::mod1"));
@@ -613,4 +1106,316 @@ mod tests {
         assert_eq!(dirs[0].level, LevelFilter::max());
         assert!(filter.is_some() && filter.unwrap().to_string() == "a*c");
     }
+
+    #[test]
+    fn parse_spec_rate_limit() {
+        let (dirs, filter) = parse_spec("crate1=warn~5,crate2~100:10s,crate3=info~1:250ms");
+        assert_eq!(dirs.len(), 3);
+        assert_eq!(dirs[0].name, Some("crate1".to_string()));
+        assert_eq!(dirs[0].level, LevelFilter::Warn);
+        assert_eq!(
+            dirs[0].rate_limit,
+            Some(RateLimit::new(5, Duration::from_secs(1)))
+        );
+
+        assert_eq!(dirs[1].name, Some("crate2".to_string()));
+        assert_eq!(dirs[1].level, LevelFilter::max());
+        assert_eq!(
+            dirs[1].rate_limit,
+            Some(RateLimit::new(100, Duration::from_secs(10)))
+        );
+
+        assert_eq!(dirs[2].level, LevelFilter::Info);
+        assert_eq!(
+            dirs[2].rate_limit,
+            Some(RateLimit::new(1, Duration::from_millis(250)))
+        );
+        assert!(filter.is_none());
+    }
+
+    #[test]
+    fn parse_spec_invalid_rate_limit() {
+        let (dirs, _) = parse_spec("crate1=warn~lots,crate2=info~5:0s,crate3=debug~5:1d");
+        assert_eq!(dirs.len(), 3);
+        assert!(dirs.iter().all(|d| d.rate_limit.is_none()));
+        assert_eq!(dirs[0].level, LevelFilter::Warn);
+    }
+
+    #[test]
+    fn later_directive_for_same_module_wins() {
+        let filter = Builder::new()
+            .filter(Some("crate1"), LevelFilter::Warn)
+            .parse("crate1=debug,crate2=info")
+            .filter(Some("crate2"), LevelFilter::Error)
+            .build();
+
+        assert_eq!(filter.directives().len(), 2);
+        assert!(enabled(&filter.directives, Level::Debug, "crate1"));
+        assert!(!enabled(&filter.directives, Level::Info, "crate2"));
+    }
+
+    #[test]
+    fn later_sources_win_regardless_of_order() {
+        let filter = Builder::new()
+            .parse_with_source("crate1=trace,crate2=trace", Source::Override)
+            .parse_with_source("crate1=info,crate3=info", Source::Environment)
+            .filter(Some("crate1"), LevelFilter::Error)
+            .filter(Some("crate2"), LevelFilter::Error)
+            .filter(Some("crate3"), LevelFilter::Error)
+            .filter_with_source(None, LevelFilter::Warn, Source::Default)
+            .build();
+
+        let effective = filter
+            .directives()
+            .iter()
+            .map(|d| (d.module(), d.level(), d.source()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            effective,
+            vec![
+                (None, LevelFilter::Warn, Source::Default),
+                (Some("crate1"), LevelFilter::Trace, Source::Override),
+                (Some("crate2"), LevelFilter::Trace, Source::Override),
+                (Some("crate3"), LevelFilter::Info, Source::Environment),
+            ]
+        );
+    }
+
+    #[test]
+    fn default_directive_has_default_source() {
+        let filter = Builder::new().build();
+
+        assert_eq!(filter.directives().len(), 1);
+        assert_eq!(filter.directives()[0].source(), super::Source::Default);
+        assert_eq!(filter.directives()[0].level(), LevelFilter::Error);
+    }
+
+    #[test]
+    fn regex_filter_from_later_source_wins() {
+        let filter = Builder::new()
+            .parse_with_source("info/from env", Source::Environment)
+            .parse("debug/from code")
+            .parse("trace")
+            .build();
+
+        assert_eq!(
+            filter.filter.as_ref().map(ToString::to_string),
+            Some("from env".to_owned())
+        );
+    }
+
+    #[test]
+    fn verbosity_shifts_default_level() {
+        let level = |default, delta| Builder::new().verbosity(default, delta).build().filter();
+
+        assert_eq!(level(LevelFilter::Warn, 0), LevelFilter::Warn);
+        assert_eq!(level(LevelFilter::Warn, 1), LevelFilter::Info);
+        assert_eq!(level(LevelFilter::Warn, 2), LevelFilter::Debug);
+        assert_eq!(level(LevelFilter::Warn, 10), LevelFilter::Trace);
+        assert_eq!(level(LevelFilter::Warn, -1), LevelFilter::Error);
+        assert_eq!(level(LevelFilter::Warn, -10), LevelFilter::Off);
+    }
+
+    #[test]
+    fn verbosity_replaces_fallback_directive() {
+        // The order doesn't matter, the verbosity always replaces the fallback
+        let before = Builder::new()
+            .verbosity(LevelFilter::Info, -1)
+            .parse("trace~10,crate1=debug")
+            .build();
+        let after = Builder::new()
+            .parse("trace~10,crate1=debug")
+            .verbosity(LevelFilter::Info, -1)
+            .build();
+
+        for filter in &[before, after] {
+            assert_eq!(
+                filter
+                    .directives
+                    .iter()
+                    .filter(|d| d.name.is_none())
+                    .count(),
+                1
+            );
+
+            assert!(enabled(&filter.directives, Level::Warn, "crate2"));
+            assert!(!enabled(&filter.directives, Level::Info, "crate2"));
+
+            // Module directives still win for their module
+            assert!(enabled(&filter.directives, Level::Debug, "crate1"));
+
+            let fallback = log::Metadata::builder().target("crate2").build();
+            assert_eq!(
+                filter.rate_limit(&fallback),
+                Some(RateLimit::new(10, Duration::from_secs(1)))
+            );
+        }
+    }
+
+    #[test]
+    fn verbosity_keeps_fallback_directive_from_environment() {
+        let filter = Builder::new()
+            .parse_with_source("debug", Source::Environment)
+            .verbosity(LevelFilter::Warn, 0)
+            .build();
+
+        assert_eq!(filter.filter(), LevelFilter::Debug);
+
+        let filter = Builder::new()
+            .verbosity(LevelFilter::Warn, 2)
+            .parse_with_source("error,crate1=info", Source::Override)
+            .build();
+
+        assert_eq!(filter.filter(), LevelFilter::Info);
+        assert!(!enabled(&filter.directives, Level::Warn, "crate2"));
+    }
+
+    #[test]
+    fn verbosity_called_twice_uses_last() {
+        let filter = Builder::new()
+            .verbosity(LevelFilter::Info, 2)
+            .verbosity(LevelFilter::Info, 0)
+            .build();
+
+        assert_eq!(filter.filter(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn parse_spec_sample() {
+        let (dirs, _) = parse_spec("crate1=trace@0.01,crate2=debug@0.5~10,crate3~10@1");
+        assert_eq!(dirs.len(), 3);
+        assert_eq!(dirs[0].name, Some("crate1".to_string()));
+        assert_eq!(dirs[0].level, LevelFilter::Trace);
+        assert_eq!(dirs[0].sample, Some(0.01));
+        assert_eq!(dirs[0].rate_limit, None);
+
+        assert_eq!(dirs[1].level, LevelFilter::Debug);
+        assert_eq!(dirs[1].sample, Some(0.5));
+        assert_eq!(
+            dirs[1].rate_limit,
+            Some(RateLimit::new(10, Duration::from_secs(1)))
+        );
+
+        assert_eq!(dirs[2].name, Some("crate3".to_string()));
+        assert_eq!(dirs[2].sample, Some(1.0));
+        assert!(dirs[2].rate_limit.is_some());
+    }
+
+    #[test]
+    fn parse_spec_invalid_sample() {
+        let (dirs, _) = parse_spec("crate1=info@some,crate2=info@");
+        assert_eq!(dirs.len(), 2);
+        assert!(dirs.iter().all(|d| d.sample.is_none()));
+        assert_eq!(dirs[0].level, LevelFilter::Info);
+    }
+
+    #[test]
+    fn parse_spec_clamps_sample() {
+        let (dirs, _) = parse_spec("crate1=info@2,crate2=info@-0.5,crate3=info@NaN");
+        assert_eq!(dirs.len(), 3);
+        assert_eq!(dirs[0].sample, Some(1.0));
+        assert_eq!(dirs[1].sample, Some(0.0));
+        assert_eq!(dirs[2].sample, Some(0.0));
+    }
+
+    #[test]
+    fn sampled_filter_keeps_warnings() {
+        let filter = Builder::new()
+            .filter_sampled(Some("crate1"), LevelFilter::Trace, 0.0)
+            .filter_sampled(Some("crate2"), LevelFilter::Trace, 1.0)
+            .build();
+
+        let matches = |level, target| {
+            filter.matches(
+                &log::Record::builder()
+                    .level(level)
+                    .target(target)
+                    .args(format_args!(""))
+                    .build(),
+            )
+        };
+
+        assert!(!matches(Level::Info, "crate1"));
+        assert!(!matches(Level::Trace, "crate1"));
+        assert!(matches(Level::Warn, "crate1"));
+        assert!(matches(Level::Error, "crate1"));
+        assert!(matches(Level::Trace, "crate2"));
+
+        // Sampling happens when matching records, so they're still enabled
+        assert!(filter.enabled(
+            &log::Metadata::builder()
+                .level(Level::Trace)
+                .target("crate1")
+                .build()
+        ));
+    }
+
+    #[test]
+    fn sample_keeps_roughly_its_rate() {
+        let filter = Builder::new()
+            .filter_sampled(None, LevelFilter::Trace, 0.25)
+            .build();
+
+        let kept = (0..10_000)
+            .filter(|_| {
+                filter.matches(
+                    &log::Record::builder()
+                        .level(Level::Debug)
+                        .args(format_args!(""))
+                        .build(),
+                )
+            })
+            .count();
+
+        assert!(kept > 2_000 && kept < 3_000, "kept {} records", kept);
+    }
+
+    #[test]
+    fn sample_rate_is_clamped() {
+        let sample = |rate| {
+            Builder::new()
+                .filter_sampled(None, LevelFilter::Trace, rate)
+                .build()
+                .directives()[0]
+                .sample
+        };
+
+        assert_eq!(Some(0.0), sample(-1.0));
+        assert_eq!(Some(1.0), sample(2.0));
+        assert_eq!(Some(0.0), sample(std::f64::NAN));
+    }
+
+    #[test]
+    fn sampled_out_records_are_not_recordable() {
+        let filter = Builder::new()
+            .filter_sampled(None, LevelFilter::Info, 0.0)
+            .build();
+
+        let check = |level| {
+            filter.check(
+                &log::Record::builder()
+                    .level(level)
+                    .args(format_args!(""))
+                    .build(),
+            )
+        };
+
+        assert_eq!(Verdict::Filtered { recordable: false }, check(Level::Info));
+        assert_eq!(Verdict::Filtered { recordable: true }, check(Level::Debug));
+    }
+
+    #[test]
+    fn filter_rate_limit_uses_longest_match() {
+        let filter = Builder::new().parse("info~10,crate1::mod1=warn").build();
+
+        let metadata = |target| log::Metadata::builder().target(target).build();
+
+        assert_eq!(
+            filter.rate_limit(&metadata("crate2")),
+            Some(RateLimit::new(10, Duration::from_secs(1)))
+        );
+        assert_eq!(filter.rate_limit(&metadata("crate1::mod1")), None);
+        assert!(filter.has_rate_limits());
+    }
 }
@@ -4,7 +4,7 @@ use std::fmt;
 
 use self::regex::Regex;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Filter {
     inner: Regex,
 }
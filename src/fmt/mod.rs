@@ -33,7 +33,8 @@ use std::cell::RefCell;
 use std::fmt::Display;
 use std::io::prelude::*;
 use std::rc::Rc;
-use std::{fmt, io, mem};
+use std::sync::Arc;
+use std::{fmt, io};
 
 use log::Record;
 
@@ -43,10 +44,16 @@ pub(crate) mod writer;
 pub use self::humantime::glob::*;
 pub use self::writer::glob::*;
 
-use self::writer::{Buffer, Writer};
+use self::writer::{Buffer, BufferStyle, Writer};
 
 pub(crate) mod glob {
-    pub use super::{Target, TimestampPrecision, WriteStyle};
+    pub use super::{
+        BrokenPipePolicy, ConnectErrorPolicy, QueueFullPolicy, Target, Tcp, TimestampPrecision,
+        Udp, WriteStyle,
+    };
+
+    #[cfg(unix)]
+    pub use super::{Facility, Journald, Syslog, SyslogFormat};
 }
 
 /// Formatting precision of timestamps.
@@ -96,28 +103,86 @@ impl Default for TimestampPrecision {
 /// [`style`]: #method.style
 pub struct Formatter {
     buf: Rc<RefCell<Buffer>>,
-    write_style: WriteStyle,
+    buffer_style: BufferStyle,
+    message_limit: Option<usize>,
 }
 
 impl Formatter {
     pub(crate) fn new(writer: &Writer) -> Self {
         Formatter {
             buf: Rc::new(RefCell::new(writer.buffer())),
-            write_style: writer.write_style(),
+            buffer_style: writer.buffer_style(),
+            message_limit: None,
         }
     }
 
-    pub(crate) fn write_style(&self) -> WriteStyle {
-        self.write_style
+    /// Get the maximum number of bytes to write for a log message.
+    ///
+    /// The limit is configured using [`Builder::format_message_limit`].
+    /// A value of `None` means messages are never truncated.
+    ///
+    /// [`Builder::format_message_limit`]: ../struct.Builder.html#method.format_message_limit
+    pub fn message_limit(&self) -> Option<usize> {
+        self.message_limit
     }
 
-    pub(crate) fn print(&self, writer: &Writer) -> io::Result<()> {
-        writer.print(&self.buf.borrow())
+    /// Wrap a value so its output is capped at the configured [message limit].
+    ///
+    /// If the value is longer than the limit then it's cut at the last character
+    /// that fits and followed by a marker with the number of bytes left out, like
+    /// `… [truncated 48213 bytes]`. The rest of the value is only counted, not
+    /// written anywhere.
+    ///
+    /// # Examples
+    ///
+    /// Cap the length of messages written by a custom format:
+    ///
+    /// ```
+    /// use std::io::Write;
+    ///
+    /// let mut builder = env_logger::Builder::new();
+    ///
+    /// builder
+    ///     .format_message_limit(Some(1024))
+    ///     .format(|buf, record| {
+    ///         let args = buf.truncated(record.args());
+    ///
+    ///         writeln!(buf, "{}: {}", record.level(), args)
+    ///     });
+    /// ```
+    ///
+    /// [message limit]: #method.message_limit
+    pub fn truncated<T>(&self, value: T) -> Truncated<T>
+    where
+        T: Display,
+    {
+        Truncated {
+            value,
+            limit: self.message_limit,
+        }
+    }
+
+    pub(crate) fn buffer_style(&self) -> BufferStyle {
+        self.buffer_style
+    }
+
+    pub(crate) fn print(&self, writer: &Writer, record: &Record) -> io::Result<()> {
+        writer.print(&self.buf.borrow(), record)
     }
 
     pub(crate) fn clear(&mut self) {
         self.buf.borrow_mut().clear()
     }
+
+    /// A copy of the buffer written to so far.
+    pub(crate) fn buffer(&self) -> Buffer {
+        self.buf.borrow().clone()
+    }
+
+    /// The text written to the formatter so far.
+    pub(crate) fn formatted(&self) -> String {
+        String::from_utf8_lossy(self.buf.borrow().bytes()).into_owned()
+    }
 }
 
 impl Write for Formatter {
@@ -130,20 +195,100 @@ impl Write for Formatter {
     }
 }
 
+/// A value whose output is capped at a maximum number of bytes.
+///
+/// It is the result of calling [`Formatter::truncated`].
+///
+/// [`Formatter::truncated`]: struct.Formatter.html#method.truncated
+pub struct Truncated<T> {
+    value: T,
+    limit: Option<usize>,
+}
+
+impl<T: Display> Display for Truncated<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return self.value.fmt(f),
+        };
+
+        // Once the limit is reached the rest of the value is only counted,
+        // so a large value is never buffered just to find its length
+        struct TruncateWriter<'a, 'b: 'a> {
+            f: &'a mut fmt::Formatter<'b>,
+            remaining: usize,
+            truncated: usize,
+        }
+
+        impl<'a, 'b> fmt::Write for TruncateWriter<'a, 'b> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                if self.truncated > 0 {
+                    self.truncated += s.len();
+                    return Ok(());
+                }
+
+                if s.len() <= self.remaining {
+                    self.remaining -= s.len();
+                    return self.f.write_str(s);
+                }
+
+                let mut end = self.remaining;
+                while !s.is_char_boundary(end) {
+                    end -= 1;
+                }
+
+                self.remaining = 0;
+                self.truncated = s.len() - end;
+                self.f.write_str(&s[..end])
+            }
+        }
+
+        let truncated = {
+            let mut writer = TruncateWriter {
+                f,
+                remaining: limit,
+                truncated: 0,
+            };
+            fmt::write(&mut writer, format_args!("{}", self.value))?;
+
+            writer.truncated
+        };
+
+        if truncated > 0 {
+            write!(f, "… [truncated {} bytes]", truncated)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Truncated<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Truncated")
+            .field("value", &self.value)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
 impl fmt::Debug for Formatter {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Formatter").finish()
     }
 }
 
+#[allow(unknown_lints, bare_trait_objects)]
+pub(crate) type FormatFn = Arc<Fn(&mut Formatter, &Record) -> io::Result<()> + Sync + Send>;
+
+#[derive(Clone)]
 pub(crate) struct Builder {
     pub format_timestamp: Option<TimestampPrecision>,
     pub format_module_path: bool,
     pub format_level: bool,
     pub format_indent: Option<usize>,
-    #[allow(unknown_lints, bare_trait_objects)]
-    pub custom_format: Option<Box<Fn(&mut Formatter, &Record) -> io::Result<()> + Sync + Send>>,
-    built: bool,
+    pub format_message_limit: Option<usize>,
+    pub format_escape: bool,
+    pub custom_format: Option<FormatFn>,
 }
 
 impl Default for Builder {
@@ -153,8 +298,9 @@ impl Default for Builder {
             format_module_path: true,
             format_level: true,
             format_indent: Some(4),
+            format_message_limit: None,
+            format_escape: false,
             custom_format: None,
-            built: false,
         }
     }
 }
@@ -165,28 +311,27 @@ impl Builder {
     /// If the `custom_format` is `Some`, then any `default_format` switches are ignored.
     /// If the `custom_format` is `None`, then a default format is returned.
     /// Any `default_format` switches set to `false` won't be written by the format.
-    #[allow(unknown_lints, bare_trait_objects)]
-    pub fn build(&mut self) -> Box<Fn(&mut Formatter, &Record) -> io::Result<()> + Sync + Send> {
-        assert!(!self.built, "attempt to re-use consumed builder");
-
-        let built = mem::replace(
-            self,
-            Builder {
-                built: true,
-                ..Default::default()
-            },
-        );
+    pub fn build(&self) -> FormatFn {
+        let built = self.clone();
+        let message_limit = built.format_message_limit;
 
         if let Some(fmt) = built.custom_format {
-            fmt
+            Arc::new(move |buf, record| {
+                buf.message_limit = message_limit;
+
+                fmt(buf, record)
+            })
         } else {
-            Box::new(move |buf, record| {
+            Arc::new(move |buf, record| {
+                buf.message_limit = message_limit;
+
                 let fmt = DefaultFormat {
                     timestamp: built.format_timestamp,
                     module_path: built.format_module_path,
                     level: built.format_level,
                     written_header_value: false,
                     indent: built.format_indent,
+                    escape: built.format_escape,
                     buf,
                 };
 
@@ -210,6 +355,7 @@ struct DefaultFormat<'a> {
     level: bool,
     written_header_value: bool,
     indent: Option<usize>,
+    escape: bool,
     buf: &'a mut Formatter,
 }
 
@@ -316,9 +462,26 @@ impl<'a> DefaultFormat<'a> {
     }
 
     fn write_args(&mut self, record: &Record) -> io::Result<()> {
+        let args = self.buf.truncated(record.args());
+
+        if self.escape {
+            // Messages are truncated before they're escaped, so escapes aren't cut short
+            self.write_message(Escaped {
+                value: args,
+                newlines: self.indent.is_some(),
+            })
+        } else {
+            self.write_message(args)
+        }
+    }
+
+    fn write_message<T>(&mut self, args: T) -> io::Result<()>
+    where
+        T: Display,
+    {
         match self.indent {
             // Fast path for no indentation
-            None => writeln!(self.buf, "{}", record.args()),
+            None => writeln!(self.buf, "{}", args),
 
             Some(indent_count) => {
                 // Create a wrapper around the buffer only if we have to actually indent the message
@@ -353,7 +516,7 @@ impl<'a> DefaultFormat<'a> {
                         fmt: self,
                         indent_count,
                     };
-                    write!(wrapper, "{}", record.args())?;
+                    write!(wrapper, "{}", args)?;
                 }
 
                 writeln!(self.buf)?;
@@ -364,6 +527,61 @@ impl<'a> DefaultFormat<'a> {
     }
 }
 
+/// A message with its control characters escaped.
+///
+/// Tabs are kept as-is, and so are newlines if they're allowed, like when the
+/// lines of a message are indented. Other control characters, including the
+/// escape character that starts ANSI sequences, are written using Rust's escape
+/// syntax so they can't move the cursor or change the terminal style. Characters
+/// that change the direction of text are escaped too.
+struct Escaped<T> {
+    value: T,
+    newlines: bool,
+}
+
+impl<T: Display> Display for Escaped<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        struct EscapeWriter<'a, 'b: 'a> {
+            f: &'a mut fmt::Formatter<'b>,
+            newlines: bool,
+        }
+
+        impl<'a, 'b> fmt::Write for EscapeWriter<'a, 'b> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                let mut start = 0;
+                for (i, c) in s.char_indices() {
+                    if !needs_escape(c, self.newlines) {
+                        continue;
+                    }
+
+                    self.f.write_str(&s[start..i])?;
+                    for escaped in c.escape_default() {
+                        fmt::Write::write_char(self.f, escaped)?;
+                    }
+                    start = i + c.len_utf8();
+                }
+
+                self.f.write_str(&s[start..])
+            }
+        }
+
+        let mut writer = EscapeWriter {
+            f,
+            newlines: self.newlines,
+        };
+        fmt::write(&mut writer, format_args!("{}", self.value))
+    }
+}
+
+fn needs_escape(c: char, newlines: bool) -> bool {
+    match c {
+        '\n' => !newlines,
+        '\t' => false,
+        '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => true,
+        c => c.is_control(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -371,10 +589,14 @@ mod tests {
     use log::{Level, Record};
 
     fn write(fmt: DefaultFormat) -> String {
+        write_args(fmt, format_args!("log\nmessage"))
+    }
+
+    fn write_args(fmt: DefaultFormat, args: fmt::Arguments) -> String {
         let buf = fmt.buf.buf.clone();
 
         let record = Record::builder()
-            .args(format_args!("log\nmessage"))
+            .args(args)
             .level(Level::Info)
             .file(Some("test.rs"))
             .line(Some(144))
@@ -401,6 +623,7 @@ mod tests {
             level: true,
             written_header_value: false,
             indent: None,
+            escape: false,
             buf: &mut f,
         });
 
@@ -421,6 +644,7 @@ mod tests {
             level: false,
             written_header_value: false,
             indent: None,
+            escape: false,
             buf: &mut f,
         });
 
@@ -441,6 +665,7 @@ mod tests {
             level: true,
             written_header_value: false,
             indent: Some(4),
+            escape: false,
             buf: &mut f,
         });
 
@@ -461,6 +686,7 @@ mod tests {
             level: true,
             written_header_value: false,
             indent: Some(0),
+            escape: false,
             buf: &mut f,
         });
 
@@ -481,9 +707,163 @@ mod tests {
             level: false,
             written_header_value: false,
             indent: Some(4),
+            escape: false,
+            buf: &mut f,
+        });
+
+        assert_eq!("log\n    message\n", written);
+    }
+
+    #[test]
+    fn format_message_limit() {
+        let writer = writer::Builder::new()
+            .write_style(WriteStyle::Never)
+            .build();
+
+        let mut f = Formatter::new(&writer);
+        f.message_limit = Some(5);
+
+        let written = write_args(
+            DefaultFormat {
+                timestamp: None,
+                module_path: false,
+                level: false,
+                written_header_value: false,
+                indent: None,
+                escape: false,
+                buf: &mut f,
+            },
+            format_args!("{}", "a long message"),
+        );
+
+        assert_eq!("a lon… [truncated 9 bytes]\n", written);
+    }
+
+    #[test]
+    fn format_message_limit_not_exceeded() {
+        let writer = writer::Builder::new()
+            .write_style(WriteStyle::Never)
+            .build();
+
+        let mut f = Formatter::new(&writer);
+        f.message_limit = Some(11);
+
+        let written = write(DefaultFormat {
+            timestamp: None,
+            module_path: false,
+            level: false,
+            written_header_value: false,
+            indent: Some(4),
+            escape: false,
             buf: &mut f,
         });
 
         assert_eq!("log\n    message\n", written);
     }
+
+    #[test]
+    fn truncated_respects_char_boundaries() {
+        let truncated = Truncated {
+            value: "αβγ",
+            limit: Some(3),
+        };
+
+        assert_eq!("α… [truncated 4 bytes]", truncated.to_string());
+    }
+
+    #[test]
+    fn truncated_counts_writes_past_the_limit() {
+        struct Repeated;
+
+        impl Display for Repeated {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                for _ in 0..10_000 {
+                    f.write_str("more ")?;
+                }
+
+                Ok(())
+            }
+        }
+
+        let truncated = Truncated {
+            value: Repeated,
+            limit: Some(8),
+        };
+
+        assert_eq!("more mor… [truncated 49992 bytes]", truncated.to_string());
+    }
+
+    #[test]
+    fn format_escape() {
+        let writer = writer::Builder::new()
+            .write_style(WriteStyle::Never)
+            .build();
+
+        let mut f = Formatter::new(&writer);
+
+        let written = write_args(
+            DefaultFormat {
+                timestamp: None,
+                module_path: false,
+                level: false,
+                written_header_value: false,
+                indent: Some(4),
+                escape: true,
+                buf: &mut f,
+            },
+            format_args!("{}", "a\r\x1b[31mred\x1b[0m\tmulti\nline\u{202e}"),
+        );
+
+        assert_eq!(
+            "a\\r\\u{1b}[31mred\\u{1b}[0m\tmulti\n    line\\u{202e}\n",
+            written
+        );
+    }
+
+    #[test]
+    fn format_escape_newlines_without_indent() {
+        let writer = writer::Builder::new()
+            .write_style(WriteStyle::Never)
+            .build();
+
+        let mut f = Formatter::new(&writer);
+
+        let written = write(DefaultFormat {
+            timestamp: None,
+            module_path: false,
+            level: false,
+            written_header_value: false,
+            indent: None,
+            escape: true,
+            buf: &mut f,
+        });
+
+        assert_eq!("log\\nmessage\n", written);
+    }
+
+    #[test]
+    fn format_escape_truncated() {
+        let writer = writer::Builder::new()
+            .write_style(WriteStyle::Never)
+            .build();
+
+        let mut f = Formatter::new(&writer);
+        f.message_limit = Some(2);
+
+        let written = write_args(
+            DefaultFormat {
+                timestamp: None,
+                module_path: false,
+                level: false,
+                written_header_value: false,
+                indent: None,
+                escape: true,
+                buf: &mut f,
+            },
+            format_args!("{}", "\x1b[0m"),
+        );
+
+        // The escape for the first character isn't cut short
+        assert_eq!("\\u{1b}[… [truncated 2 bytes]\n", written);
+    }
 }
@@ -0,0 +1,268 @@
+/*
+This internal module contains the background writer.
+
+Formatted buffers are pushed onto a bounded queue and printed by a dedicated
+thread, so the threads producing log records don't block on a slow terminal
+or pipe.
+*/
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use super::termcolor::{Buffer, BufferWriter};
+
+/// What to do with a record when the background writer's queue is full.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum QueueFullPolicy {
+    /// Wait for space in the queue.
+    Block,
+    /// Discard the record being logged.
+    DropNewest,
+    /// Discard the oldest record in the queue to make space.
+    DropOldest,
+}
+
+pub(in crate::fmt::writer) struct Background {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // Signalled when a buffer is pushed or the queue is closed
+    not_empty: Condvar,
+    // Signalled when a buffer is popped
+    not_full: Condvar,
+    // Signalled when the queue is drained and the last buffer has been printed
+    idle: Condvar,
+}
+
+struct State {
+    queue: VecDeque<Buffer>,
+    capacity: usize,
+    policy: QueueFullPolicy,
+    printing: bool,
+    dropped: u64,
+    closed: bool,
+}
+
+impl Background {
+    pub(in crate::fmt::writer) fn spawn(
+        writer: Arc<BufferWriter>,
+        capacity: usize,
+        policy: QueueFullPolicy,
+    ) -> Option<Self> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::with_capacity(capacity),
+                capacity: capacity.max(1),
+                policy,
+                printing: false,
+                dropped: 0,
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            idle: Condvar::new(),
+        });
+
+        let handle = {
+            let shared = shared.clone();
+
+            thread::Builder::new()
+                .name("env_logger".into())
+                .spawn(move || run(&shared, &writer))
+                .ok()?
+        };
+
+        Some(Background {
+            shared,
+            handle: Some(handle),
+        })
+    }
+
+    pub(in crate::fmt::writer) fn send(&self, buf: &Buffer) {
+        let mut state = self.shared.lock();
+
+        if state.queue.len() >= state.capacity {
+            match state.policy {
+                QueueFullPolicy::Block => {
+                    while state.queue.len() >= state.capacity && !state.closed {
+                        state = self
+                            .shared
+                            .not_full
+                            .wait(state)
+                            .unwrap_or_else(|e| e.into_inner());
+                    }
+                }
+                QueueFullPolicy::DropNewest => {
+                    state.dropped += 1;
+                    return;
+                }
+                QueueFullPolicy::DropOldest => {
+                    state.queue.pop_front();
+                    state.dropped += 1;
+                }
+            }
+        }
+
+        state.queue.push_back(buf.clone());
+        self.shared.not_empty.notify_one();
+    }
+
+    /// Wait for all queued buffers to be printed.
+    pub(in crate::fmt::writer) fn flush(&self) {
+        let mut state = self.shared.lock();
+
+        while !state.queue.is_empty() || state.printing {
+            state = self
+                .shared
+                .idle
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.not_empty.notify_one();
+        self.shared.not_full.notify_all();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic while holding the lock can't leave the queue in an invalid state
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn run(shared: &Shared, writer: &BufferWriter) {
+    loop {
+        let (buf, dropped) = {
+            let mut state = shared.lock();
+
+            while state.queue.is_empty() && !state.closed {
+                state = shared
+                    .not_empty
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+
+            let buf = match state.queue.pop_front() {
+                Some(buf) => buf,
+                None => return,
+            };
+
+            state.printing = true;
+            shared.not_full.notify_one();
+
+            (buf, std::mem::replace(&mut state.dropped, 0))
+        };
+
+        if dropped > 0 {
+            let mut notice = writer.buffer();
+            let _ = notice.write(
+                format!(
+                    "[env_logger] dropped {} log records because the queue was full\n",
+                    dropped
+                )
+                .as_bytes(),
+            );
+            let _ = writer.print(&notice);
+        }
+
+        let _ = writer.print(&buf);
+
+        let mut state = shared.lock();
+        state.printing = false;
+        if state.queue.is_empty() {
+            shared.idle.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fmt::WriteStyle;
+
+    fn test_writer() -> Arc<BufferWriter> {
+        Arc::new(BufferWriter::stderr(true, WriteStyle::Never))
+    }
+
+    // A background writer without a thread draining its queue
+    fn paused(capacity: usize, policy: QueueFullPolicy) -> Background {
+        Background {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    queue: VecDeque::new(),
+                    capacity,
+                    policy,
+                    printing: false,
+                    dropped: 0,
+                    closed: false,
+                }),
+                not_empty: Condvar::new(),
+                not_full: Condvar::new(),
+                idle: Condvar::new(),
+            }),
+            handle: None,
+        }
+    }
+
+    #[test]
+    fn flush_waits_for_queue_to_drain() {
+        let writer = test_writer();
+        let background = Background::spawn(writer.clone(), 4, QueueFullPolicy::Block)
+            .expect("failed to spawn background writer");
+
+        let buf = writer.buffer();
+        for _ in 0..16 {
+            background.send(&buf);
+        }
+
+        background.flush();
+
+        let state = background.shared.lock();
+        assert!(state.queue.is_empty());
+        assert!(!state.printing);
+        assert_eq!(0, state.dropped);
+    }
+
+    #[test]
+    fn drop_oldest_counts_dropped_buffers() {
+        let background = paused(2, QueueFullPolicy::DropOldest);
+
+        let buf = test_writer().buffer();
+        for _ in 0..5 {
+            background.send(&buf);
+        }
+
+        let state = background.shared.lock();
+        assert_eq!(2, state.queue.len());
+        assert_eq!(3, state.dropped);
+    }
+
+    #[test]
+    fn drop_newest_counts_dropped_buffers() {
+        let background = paused(3, QueueFullPolicy::DropNewest);
+
+        let buf = test_writer().buffer();
+        for _ in 0..5 {
+            background.send(&buf);
+        }
+
+        let state = background.shared.lock();
+        assert_eq!(3, state.queue.len());
+        assert_eq!(2, state.dropped);
+    }
+}
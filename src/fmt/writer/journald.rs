@@ -0,0 +1,443 @@
+/*
+This internal module contains the journald writer.
+
+Records are sent to the systemd journal using its native protocol, where each
+datagram is a set of fields. The formatted record is sent as the `MESSAGE` field
+alongside the record's level, source location and target.
+
+This module also detects when `stderr` is connected to the journal, so lines
+written to it can be prefixed with their priority.
+*/
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::mem::ManuallyDrop;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+
+use log::Record;
+
+use super::syslog::{app_name, severity, Datagram};
+use super::termcolor::Buffer;
+
+/// The default path of the journal's native socket.
+pub const DEFAULT_JOURNALD_PATH: &str = "/run/systemd/journal/socket";
+
+/// The systemd journal to send log records to.
+///
+/// Records are formatted using the logger's format and sent as the `MESSAGE` field
+/// of a journal entry. Entries also get these fields:
+///
+/// - `PRIORITY`: the level of the record mapped to a syslog severity.
+/// - `SYSLOG_IDENTIFIER`: the name of the current executable.
+/// - `TARGET`: the target of the record.
+/// - `CODE_MODULE`, `CODE_FILE` and `CODE_LINE`: where the record was logged, if known.
+///
+/// With the `kv` feature, the key-value pairs of a record are sent as fields too.
+/// Keys are converted to uppercase and any characters other than ASCII letters,
+/// digits and underscores are replaced with underscores. Keys that don't start
+/// with a letter after that aren't sent.
+///
+/// The journal records the time of each entry, so the timestamp in the default
+/// format can usually be turned off with [`Builder::format_timestamp`].
+///
+/// Each entry is sent as a single datagram, so records larger than the socket's
+/// maximum datagram size can't be sent. They fail with an error that's handled by
+/// the logger's [`WriteErrorPolicy`], like any other error writing a record.
+///
+/// If the socket can't be created, records are written to the terminal target
+/// instead. See [`ConnectErrorPolicy`] for the other options.
+///
+/// # Examples
+///
+/// Send log records to the journal:
+///
+/// ```
+/// use env_logger::{Builder, Journald};
+///
+/// let mut builder = Builder::new();
+///
+/// builder
+///     .format_timestamp(None)
+///     .journald(Journald::new());
+/// ```
+///
+/// [`Builder::format_timestamp`]: ../struct.Builder.html#method.format_timestamp
+/// [`WriteErrorPolicy`]: ../enum.WriteErrorPolicy.html
+/// [`ConnectErrorPolicy`]: ../enum.ConnectErrorPolicy.html
+#[derive(Clone, Debug)]
+pub struct Journald {
+    path: PathBuf,
+    syslog_identifier: Option<String>,
+}
+
+impl Journald {
+    /// Send records to the journal listening on `/run/systemd/journal/socket`.
+    pub fn new() -> Self {
+        Journald {
+            path: DEFAULT_JOURNALD_PATH.into(),
+            syslog_identifier: None,
+        }
+    }
+
+    /// Set the path of the socket the journal is listening on.
+    pub fn path<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.path = path.as_ref().to_owned();
+        self
+    }
+
+    /// Set the `SYSLOG_IDENTIFIER` field sent with entries.
+    ///
+    /// The name of the current executable is used by default.
+    pub fn syslog_identifier<S>(mut self, syslog_identifier: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.syslog_identifier = Some(syslog_identifier.into());
+        self
+    }
+
+    pub(in crate::fmt::writer) fn connect(self) -> io::Result<(Framing, Datagram)> {
+        let framing = Framing {
+            syslog_identifier: self.syslog_identifier.unwrap_or_else(app_name),
+        };
+
+        Ok((framing, Datagram::unbound(self.path)?))
+    }
+}
+
+impl Default for Journald {
+    fn default() -> Self {
+        Journald::new()
+    }
+}
+
+pub(in crate::fmt::writer) struct Framing {
+    syslog_identifier: String,
+}
+
+impl Framing {
+    pub(in crate::fmt::writer) fn frame(
+        &self,
+        record: &Record,
+        message: &[u8],
+        out: &mut Buffer,
+    ) -> io::Result<()> {
+        let message = match message.last() {
+            Some(b'\n') => &message[..message.len() - 1],
+            _ => message,
+        };
+
+        write_field(
+            out,
+            "PRIORITY",
+            severity(record.level()).to_string().as_bytes(),
+        )?;
+        write_field(out, "MESSAGE", message)?;
+        write_field(out, "SYSLOG_IDENTIFIER", self.syslog_identifier.as_bytes())?;
+        write_field(out, "TARGET", record.target().as_bytes())?;
+
+        if let Some(module_path) = record.module_path() {
+            write_field(out, "CODE_MODULE", module_path.as_bytes())?;
+        }
+
+        if let Some(file) = record.file() {
+            write_field(out, "CODE_FILE", file.as_bytes())?;
+        }
+
+        if let Some(line) = record.line() {
+            write_field(out, "CODE_LINE", line.to_string().as_bytes())?;
+        }
+
+        #[cfg(feature = "kv")]
+        {
+            use log::kv::{self, Key, Value, Visitor};
+
+            struct FieldVisitor<'a> {
+                out: &'a mut Buffer,
+            }
+
+            impl<'a, 'kvs> Visitor<'kvs> for FieldVisitor<'a> {
+                fn visit_pair(
+                    &mut self,
+                    key: Key<'kvs>,
+                    value: Value<'kvs>,
+                ) -> Result<(), kv::Error> {
+                    if let Some(name) = field_name(key.as_str()) {
+                        write_field(self.out, &name, value.to_string().as_bytes())?;
+                    }
+
+                    Ok(())
+                }
+            }
+
+            record
+                .key_values()
+                .visit(&mut FieldVisitor { out })
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a field using the journal's native protocol.
+///
+/// Values containing newlines are written with their length in binary.
+fn write_field(out: &mut Buffer, name: &str, value: &[u8]) -> io::Result<()> {
+    out.write(name.as_bytes())?;
+
+    if value.contains(&b'\n') {
+        out.write(b"\n")?;
+        let len = value.len() as u64;
+        for i in 0..8 {
+            out.write(&[(len >> (8 * i)) as u8])?;
+        }
+    } else {
+        out.write(b"=")?;
+    }
+
+    out.write(value)?;
+    out.write(b"\n")?;
+
+    Ok(())
+}
+
+/// Convert a key into a valid journal field name.
+#[cfg(any(feature = "kv", test))]
+fn field_name(key: &str) -> Option<String> {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => Some(name),
+        _ => None,
+    }
+}
+
+/// Whether `stderr` is connected to the journal.
+///
+/// systemd sets the `JOURNAL_STREAM` variable to the device and inode of the
+/// stream it connects to a service's output.
+pub(in crate::fmt::writer) fn is_stderr_journal_stream() -> bool {
+    match env::var("JOURNAL_STREAM") {
+        Ok(stream) => is_journal_stream(&stream, &io::stderr()),
+        Err(_) => false,
+    }
+}
+
+/// Whether a file is the stream described by a `JOURNAL_STREAM` value.
+fn is_journal_stream<F>(stream: &str, file: &F) -> bool
+where
+    F: AsRawFd,
+{
+    let mut parts = stream.splitn(2, ':');
+    let (dev, ino) = match (
+        parts.next().and_then(|dev| dev.parse::<u64>().ok()),
+        parts.next().and_then(|ino| ino.parse::<u64>().ok()),
+    ) {
+        (Some(dev), Some(ino)) => (dev, ino),
+        _ => return false,
+    };
+
+    // Safety: the descriptor stays open while it's borrowed, and isn't closed
+    // when the `File` is forgotten
+    let file = ManuallyDrop::new(unsafe { File::from_raw_fd(file.as_raw_fd()) });
+
+    file.metadata()
+        .map(|metadata| metadata.dev() == dev && metadata.ino() == ino)
+        .unwrap_or(false)
+}
+
+/// Prefix each line of a message with its priority.
+///
+/// The journal reads the `<N>` prefix on lines written to a stream as the priority.
+pub(in crate::fmt::writer) fn prefix_lines(
+    record: &Record,
+    message: &[u8],
+    out: &mut Buffer,
+) -> io::Result<()> {
+    let prefix = format!("<{}>", severity(record.level()));
+
+    let mut rest = message;
+    while !rest.is_empty() {
+        let end = match rest.iter().position(|&b| b == b'\n') {
+            Some(i) => i + 1,
+            None => rest.len(),
+        };
+
+        out.write(prefix.as_bytes())?;
+        out.write(&rest[..end])?;
+        rest = &rest[end..];
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::net::UnixDatagram;
+    use std::process;
+
+    use log::Level;
+
+    use crate::fmt::writer::Builder;
+
+    fn bind(name: &str) -> (UnixDatagram, PathBuf) {
+        let path = env::temp_dir().join(format!("env_logger-{}-{}.sock", name, process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let socket = UnixDatagram::bind(&path).expect("failed to bind socket");
+        (socket, path)
+    }
+
+    fn recv(socket: &UnixDatagram) -> Vec<u8> {
+        let mut buf = [0; 1024];
+        let len = socket.recv(&mut buf).expect("failed to receive record");
+
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn journald_fields() {
+        let (socket, path) = bind("journald");
+
+        let writer = Builder::new()
+            .journald(Journald::new().path(&path).syslog_identifier("app"))
+            .build();
+
+        let mut buf = writer.buffer();
+        buf.write(b"a message\n").unwrap();
+
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("test::target")
+            .module_path(Some("test::path"))
+            .file(Some("test.rs"))
+            .line(Some(144))
+            .build();
+        writer.print(&buf, &record).expect("failed to send record");
+
+        assert_eq!(
+            &b"PRIORITY=4\nMESSAGE=a message\nSYSLOG_IDENTIFIER=app\nTARGET=test::target\nCODE_MODULE=test::path\nCODE_FILE=test.rs\nCODE_LINE=144\n"[..],
+            &*recv(&socket)
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn journald_multiline_message() {
+        let (socket, path) = bind("journald-multiline");
+
+        let writer = Builder::new()
+            .journald(Journald::new().path(&path).syslog_identifier("app"))
+            .build();
+
+        let mut buf = writer.buffer();
+        buf.write(b"a\nmessage\n").unwrap();
+
+        let record = Record::builder().level(Level::Error).target("t").build();
+        writer.print(&buf, &record).expect("failed to send record");
+
+        let mut expected = b"PRIORITY=3\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nmessage\nSYSLOG_IDENTIFIER=app\nTARGET=t\n");
+
+        assert_eq!(expected, recv(&socket));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn field_name_from_key() {
+        assert_eq!(Some("USER_ID".to_owned()), field_name("user.id"));
+        assert_eq!(Some("A1".to_owned()), field_name("a1"));
+        assert_eq!(None, field_name("_private"));
+        assert_eq!(None, field_name("1st"));
+        assert_eq!(None, field_name(""));
+    }
+
+    #[test]
+    fn prefix_lines_with_priority() {
+        let writer = Builder::new().pipe(Box::new(io::sink())).build();
+
+        let mut out = writer.buffer();
+        let record = Record::builder().level(Level::Info).build();
+        prefix_lines(&record, b"a\nmessage\n", &mut out).unwrap();
+
+        assert_eq!(&b"<6>a\n<6>message\n"[..], out.bytes());
+    }
+
+    #[test]
+    fn journal_stream_matches_device_and_inode() {
+        let path = env::temp_dir().join(format!("env_logger-stream-{}", process::id()));
+        let file = File::create(&path).expect("failed to create file");
+        let metadata = file.metadata().expect("failed to read file metadata");
+
+        let stream = format!("{}:{}", metadata.dev(), metadata.ino());
+        assert!(is_journal_stream(&stream, &file));
+
+        assert!(!is_journal_stream("0:0", &file));
+        assert!(!is_journal_stream("a stream", &file));
+        assert!(!is_journal_stream("", &file));
+
+        // The file is still open
+        assert!(file.metadata().is_ok());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn oversized_records_are_write_errors() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use log::{LevelFilter, Log};
+
+        use crate::WriteErrorPolicy;
+
+        let (_socket, path) = bind("journald-oversized");
+
+        let errors = Arc::new(AtomicUsize::new(0));
+        let logger = {
+            let errors = errors.clone();
+
+            crate::Builder::new()
+                .filter_level(LevelFilter::Info)
+                .journald(Journald::new().path(&path))
+                .write_error_policy(WriteErrorPolicy::callback(move |_, _| {
+                    errors.fetch_add(1, Ordering::SeqCst);
+                }))
+                .build()
+        };
+
+        let message = "x".repeat(16 * 1024 * 1024);
+        logger.log(
+            &Record::builder()
+                .level(Level::Info)
+                .args(format_args!("{}", message))
+                .build(),
+        );
+
+        assert_eq!(1, errors.load(Ordering::SeqCst));
+        assert_eq!(1, logger.stats().write_errors());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
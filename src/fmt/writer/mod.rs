@@ -1,16 +1,34 @@
 mod atty;
+mod background;
+#[cfg(unix)]
+mod journald;
+mod net;
+#[cfg(unix)]
+mod syslog;
 mod termcolor;
 
 use self::atty::{is_stderr, is_stdout};
+use self::background::Background;
 use self::termcolor::BufferWriter;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::{fmt, io};
 
+use log::{Level, Record};
+
+pub use self::background::QueueFullPolicy;
+#[cfg(unix)]
+pub use self::journald::{Journald, DEFAULT_JOURNALD_PATH};
+pub use self::net::{Tcp, Udp};
+#[cfg(unix)]
+pub use self::syslog::{Facility, Syslog, SyslogFormat, DEFAULT_SYSLOG_PATH};
+
 pub(in crate::fmt) mod glob {
     pub use super::termcolor::glob::*;
     pub use super::*;
 }
 
-pub(in crate::fmt) use self::termcolor::Buffer;
+pub(crate) use self::termcolor::Buffer;
 
 /// Log target, either `stdout` or `stderr`.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -44,34 +62,237 @@ impl Default for WriteStyle {
     }
 }
 
+/// What to do when a target's reader has gone away.
+///
+/// This happens when the output of a program is piped into another one that
+/// exits before reading all of it, like `head`. Writing to the target then fails
+/// with a broken pipe error.
+///
+/// Records written by a background thread aren't covered by the policy.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BrokenPipePolicy {
+    /// Handle it like any other write error, and keep writing to the target.
+    Report,
+    /// Handle the first error like any other write error, then stop writing
+    /// to the target.
+    ///
+    /// Records that are discarded after that are counted as filtered, not emitted.
+    Disable,
+    /// Write the record, and every record after it, to `stderr` instead.
+    ///
+    /// If the target is `stderr`, records are discarded.
+    FallBackToStderr,
+    /// Exit the process quietly, with a successful status.
+    ///
+    /// The record is still written to the logger's other outputs, and they're
+    /// flushed before exiting. The process exits from inside the logger, so
+    /// destructors don't run and anything else the program has buffered, like
+    /// its own output to `stdout`, is lost.
+    Exit,
+}
+
+impl Default for BrokenPipePolicy {
+    fn default() -> Self {
+        BrokenPipePolicy::Report
+    }
+}
+
+/// What to do when a socket for a syslog, journald or UDP target can't be created.
+///
+/// Sockets are created when the logger is built. A TCP target connects when
+/// records are written instead, so it isn't covered by the policy.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ConnectErrorPolicy {
+    /// Write records to the terminal target instead, which is `stderr` unless
+    /// it's been set.
+    ///
+    /// A warning with the error is printed to `stderr` the first time it happens.
+    FallBackToTarget,
+    /// Keep the target, and handle each record written to it like a write error,
+    /// with the error from creating the socket.
+    Report,
+}
+
+impl Default for ConnectErrorPolicy {
+    fn default() -> Self {
+        ConnectErrorPolicy::FallBackToTarget
+    }
+}
+
+/// An arbitrary writer that log records can be sent to instead of a terminal.
+pub(in crate::fmt::writer) type Pipe = Mutex<Box<dyn io::Write + Send>>;
+
 /// A terminal target with color awareness.
 pub(crate) struct Writer {
-    inner: BufferWriter,
-    write_style: WriteStyle,
+    inner: Arc<BufferWriter>,
+    background: Option<Background>,
+    buffer_style: BufferStyle,
+    framing: Option<Framing>,
+    // Records less severe than the level are written to the other writer
+    split: Option<(Level, Box<Writer>)>,
+    broken_pipe: BrokenPipePolicy,
+    // Set once the target has failed with a broken pipe
+    broken: AtomicBool,
+    // Where records go once the target is broken, if anywhere
+    fallback: Option<BufferWriter>,
+}
+
+/// A protocol that formatted records are wrapped in before they're printed.
+enum Framing {
+    #[cfg(unix)]
+    Syslog(self::syslog::Framing),
+    #[cfg(unix)]
+    Journald(self::journald::Framing),
+    // Lines written to a stream connected to the journal get a priority prefix
+    #[cfg(unix)]
+    JournalStream,
+}
+
+/// The kind of buffer a writer prints.
+///
+/// Writers with the same buffer style can print the same formatted buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct BufferStyle {
+    color_choice: WriteStyle,
+    is_test: bool,
 }
 
 impl Writer {
-    pub fn write_style(&self) -> WriteStyle {
-        self.write_style
+    /// Get the writer to print a record at the given level to.
+    pub(crate) fn select(&self, level: Level) -> &Writer {
+        match self.split {
+            Some((threshold, ref writer)) if level > threshold => writer,
+            _ => self,
+        }
+    }
+
+    pub(crate) fn buffer_style(&self) -> BufferStyle {
+        self.buffer_style
     }
 
-    pub(in crate::fmt) fn buffer(&self) -> Buffer {
+    pub(crate) fn buffer(&self) -> Buffer {
         self.inner.buffer()
     }
 
-    pub(in crate::fmt) fn print(&self, buf: &Buffer) -> io::Result<()> {
-        self.inner.print(buf)
+    pub(crate) fn print(&self, buf: &Buffer, record: &Record) -> io::Result<()> {
+        if self.broken.load(Ordering::Relaxed) {
+            return self.print_fallback(buf);
+        }
+
+        let result = match self.framing {
+            Some(ref framing) => {
+                let mut framed = self.inner.buffer();
+                framing.frame(record, buf.bytes(), &mut framed)?;
+
+                self.send(&framed)
+            }
+            None => self.send(buf),
+        };
+
+        match result {
+            Err(e)
+                if e.kind() == io::ErrorKind::BrokenPipe
+                    && self.broken_pipe != BrokenPipePolicy::Report =>
+            {
+                self.broken.store(true, Ordering::Relaxed);
+
+                match self.broken_pipe {
+                    // The logger exits once the record has been written everywhere else
+                    BrokenPipePolicy::Exit => Ok(()),
+                    BrokenPipePolicy::FallBackToStderr => self.print_fallback(buf),
+                    _ => Err(e),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// Whether records are discarded because the target is broken.
+    pub(crate) fn is_discarding(&self) -> bool {
+        self.broken.load(Ordering::Relaxed) && self.fallback.is_none()
+    }
+
+    /// Whether the process should exit because the target, or the writer records
+    /// are split to, is broken.
+    pub(crate) fn is_exiting(&self) -> bool {
+        let is_exiting =
+            self.broken_pipe == BrokenPipePolicy::Exit && self.broken.load(Ordering::Relaxed);
+
+        match self.split {
+            Some((_, ref writer)) => is_exiting || writer.is_exiting(),
+            None => is_exiting,
+        }
+    }
+
+    fn print_fallback(&self, buf: &Buffer) -> io::Result<()> {
+        match self.fallback {
+            Some(ref fallback) => fallback.print(buf),
+            None => Ok(()),
+        }
+    }
+
+    fn send(&self, buf: &Buffer) -> io::Result<()> {
+        if let Some(ref background) = self.background {
+            background.send(buf);
+            Ok(())
+        } else {
+            self.inner.print(buf)
+        }
+    }
+
+    /// Wait for any records queued for the background writer to be printed,
+    /// and flush the pipe they're printed to.
+    pub(crate) fn flush(&self) {
+        if let Some(ref background) = self.background {
+            background.flush();
+        }
+
+        // There's nowhere to report an error from flushing
+        let _ = self.inner.flush();
+
+        if let Some((_, ref writer)) = self.split {
+            writer.flush();
+        }
+    }
+}
+
+impl Framing {
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    fn frame(&self, record: &Record, message: &[u8], out: &mut Buffer) -> io::Result<()> {
+        match *self {
+            #[cfg(unix)]
+            Framing::Syslog(ref syslog) => syslog.frame(record.level(), message, out),
+            #[cfg(unix)]
+            Framing::Journald(ref journald) => journald.frame(record, message, out),
+            #[cfg(unix)]
+            Framing::JournalStream => self::journald::prefix_lines(record, message, out),
+        }
     }
 }
 
 /// A builder for a terminal writer.
 ///
 /// The target and style choice can be configured before building.
+///
+/// Writers built from the same builder share its pipe, so clones of a builder
+/// with a pipe write to the same place.
+#[derive(Clone)]
 pub(crate) struct Builder {
     target: Target,
+    pipe: Option<Arc<Pipe>>,
+    #[cfg(unix)]
+    syslog: Option<Syslog>,
+    #[cfg(unix)]
+    journald: Option<Journald>,
+    detect_journal_stream: bool,
+    tcp: Option<Tcp>,
+    udp: Option<Udp>,
+    split: Option<Level>,
     write_style: WriteStyle,
     is_test: bool,
-    built: bool,
+    background: Option<(usize, QueueFullPolicy)>,
+    broken_pipe: BrokenPipePolicy,
+    connect_error: ConnectErrorPolicy,
 }
 
 impl Builder {
@@ -79,18 +300,92 @@ impl Builder {
     pub(crate) fn new() -> Self {
         Builder {
             target: Default::default(),
+            pipe: None,
+            #[cfg(unix)]
+            syslog: None,
+            #[cfg(unix)]
+            journald: None,
+            detect_journal_stream: false,
+            tcp: None,
+            udp: None,
+            split: None,
             write_style: Default::default(),
             is_test: false,
-            built: false,
+            background: None,
+            broken_pipe: Default::default(),
+            connect_error: Default::default(),
         }
     }
 
     /// Set the target to write to.
     pub(crate) fn target(&mut self, target: Target) -> &mut Self {
+        self.clear_target();
         self.target = target;
         self
     }
 
+    /// Set an arbitrary writer to write to instead of a terminal target.
+    pub(crate) fn pipe(&mut self, pipe: Box<dyn io::Write + Send>) -> &mut Self {
+        self.clear_target();
+        self.pipe = Some(Arc::new(Mutex::new(pipe)));
+        self
+    }
+
+    /// Write records at the given level or more severe to `stderr` and the rest to `stdout`.
+    pub(crate) fn split(&mut self, level: Level) -> &mut Self {
+        self.clear_target();
+        self.split = Some(level);
+        self
+    }
+
+    /// Send records to a syslog daemon instead of a terminal target.
+    #[cfg(unix)]
+    pub(crate) fn syslog(&mut self, syslog: Syslog) -> &mut Self {
+        self.clear_target();
+        self.syslog = Some(syslog);
+        self
+    }
+
+    /// Send records to the systemd journal instead of a terminal target.
+    #[cfg(unix)]
+    pub(crate) fn journald(&mut self, journald: Journald) -> &mut Self {
+        self.clear_target();
+        self.journald = Some(journald);
+        self
+    }
+
+    /// Send records to a collector over TCP instead of a terminal target.
+    pub(crate) fn tcp(&mut self, tcp: Tcp) -> &mut Self {
+        self.clear_target();
+        self.tcp = Some(tcp);
+        self
+    }
+
+    /// Send records to a collector over UDP instead of a terminal target.
+    pub(crate) fn udp(&mut self, udp: Udp) -> &mut Self {
+        self.clear_target();
+        self.udp = Some(udp);
+        self
+    }
+
+    /// Whether to prefix lines with their priority when `stderr` is connected to the journal.
+    pub(crate) fn detect_journal_stream(&mut self, detect: bool) -> &mut Self {
+        self.detect_journal_stream = detect;
+        self
+    }
+
+    fn clear_target(&mut self) {
+        self.pipe = None;
+        #[cfg(unix)]
+        {
+            self.syslog = None;
+            self.journald = None;
+        }
+        self.tcp = None;
+        self.udp = None;
+        self.split = None;
+    }
+
     /// Parses a style choice string.
     ///
     /// See the [Disabling colors] section for more details.
@@ -112,17 +407,119 @@ impl Builder {
         self
     }
 
+    /// What to do when the target fails with a broken pipe.
+    pub(crate) fn broken_pipe(&mut self, policy: BrokenPipePolicy) -> &mut Self {
+        self.broken_pipe = policy;
+        self
+    }
+
+    /// What to do when a socket target can't be created.
+    pub(crate) fn connect_error(&mut self, policy: ConnectErrorPolicy) -> &mut Self {
+        self.connect_error = policy;
+        self
+    }
+
+    /// Print records on a background thread, queueing up to `capacity` records.
+    pub(crate) fn background(&mut self, capacity: usize, policy: QueueFullPolicy) -> &mut Self {
+        self.background = Some((capacity, policy));
+        self
+    }
+
     /// Build a terminal writer.
-    pub(crate) fn build(&mut self) -> Writer {
-        assert!(!self.built, "attempt to re-use consumed builder");
-        self.built = true;
+    ///
+    /// Sockets are opened again for each writer that's built.
+    pub(crate) fn build(&self) -> Writer {
+        if let Some(level) = self.split {
+            // Each stream gets its own color choice, because only one of them
+            // might be attached to a terminal
+            let mut writer = self.build_target(Target::Stderr, None);
+            let stdout = self.build_target(Target::Stdout, None);
+
+            writer.split = Some((level, Box::new(stdout)));
+            writer
+        } else {
+            let (framing, pipe) = self.framed_pipe();
+            let mut writer = self.build_target(self.target, pipe);
+
+            if framing.is_some() {
+                writer.framing = framing;
+            }
+            writer
+        }
+    }
+
+    fn framed_pipe(&self) -> (Option<Framing>, Option<Arc<Pipe>>) {
+        #[cfg(unix)]
+        {
+            if let Some(syslog) = self.syslog.clone() {
+                match syslog.connect() {
+                    Ok((framing, datagram)) => {
+                        return (Some(Framing::Syslog(framing)), Some(pipe(datagram)))
+                    }
+                    Err(e) => return self.unconnected("syslog", e),
+                }
+            }
+
+            if let Some(journald) = self.journald.clone() {
+                match journald.connect() {
+                    Ok((framing, datagram)) => {
+                        return (Some(Framing::Journald(framing)), Some(pipe(datagram)))
+                    }
+                    Err(e) => return self.unconnected("journald", e),
+                }
+            }
+        }
+
+        if let Some(tcp) = self.tcp.clone() {
+            return (None, Some(pipe(tcp.connect())));
+        }
+
+        if let Some(udp) = self.udp.clone() {
+            match udp.connect() {
+                Ok(udp) => return (None, Some(pipe(udp))),
+                Err(e) => return self.unconnected("UDP", e),
+            }
+        }
+
+        (None, self.pipe.clone())
+    }
+
+    /// The pipe to use in place of a socket that couldn't be created.
+    fn unconnected(&self, socket: &str, e: io::Error) -> (Option<Framing>, Option<Arc<Pipe>>) {
+        match self.connect_error {
+            ConnectErrorPolicy::FallBackToTarget => {
+                // Only warned about once, since each writer that's built tries again
+                static WARNED: AtomicBool = AtomicBool::new(false);
 
+                if !WARNED.swap(true, Ordering::Relaxed) {
+                    eprintln!(
+                        "warning: failed to create {} socket, logging to the terminal instead: {}",
+                        socket, e
+                    );
+                }
+
+                // Setting a socket target clears the pipe, so this is the terminal target
+                (None, self.pipe.clone())
+            }
+            ConnectErrorPolicy::Report => (
+                None,
+                Some(pipe(Unconnected {
+                    kind: e.kind(),
+                    message: format!("failed to create {} socket - {}", socket, e),
+                })),
+            ),
+        }
+    }
+
+    fn build_target(&self, target: Target, pipe: Option<Arc<Pipe>>) -> Writer {
         let color_choice = match self.write_style {
             WriteStyle::Auto => {
-                if match self.target {
-                    Target::Stderr => is_stderr(),
-                    Target::Stdout => is_stdout(),
-                } {
+                if pipe.is_none()
+                    && match target {
+                        Target::Stderr => is_stderr(),
+                        Target::Stdout => is_stdout(),
+                    }
+                {
                     WriteStyle::Auto
                 } else {
                     WriteStyle::Never
@@ -131,16 +528,104 @@ impl Builder {
             color_choice => color_choice,
         };
 
-        let writer = match self.target {
-            Target::Stderr => BufferWriter::stderr(self.is_test, color_choice),
-            Target::Stdout => BufferWriter::stdout(self.is_test, color_choice),
+        let is_pipe = pipe.is_some();
+        let buffer_style = BufferStyle {
+            color_choice,
+            is_test: self.is_test && pipe.is_none(),
+        };
+
+        let writer = match pipe {
+            Some(pipe) => BufferWriter::pipe(color_choice, pipe),
+            None => match target {
+                Target::Stderr => BufferWriter::stderr(self.is_test, color_choice),
+                Target::Stdout => BufferWriter::stdout(self.is_test, color_choice),
+            },
+        };
+
+        // The fallback prints buffers formatted for the target, so it uses the same style
+        let fallback = match self.broken_pipe {
+            BrokenPipePolicy::FallBackToStderr if is_pipe || target != Target::Stderr => {
+                Some(BufferWriter::stderr(self.is_test, color_choice))
+            }
+            _ => None,
         };
 
+        let writer = Arc::new(writer);
+        let background = self
+            .background
+            .and_then(|(capacity, policy)| Background::spawn(writer.clone(), capacity, policy));
+
         Writer {
             inner: writer,
-            write_style: self.write_style,
+            background,
+            buffer_style,
+            framing: self.journal_stream_framing(target, is_pipe),
+            split: None,
+            broken_pipe: self.broken_pipe,
+            broken: AtomicBool::new(false),
+            fallback,
+        }
+    }
+}
+
+fn pipe<W: io::Write + Send + 'static>(writer: W) -> Arc<Pipe> {
+    Arc::new(Mutex::new(Box::new(writer)))
+}
+
+/// A socket that couldn't be created, so every write fails.
+struct Unconnected {
+    kind: io::ErrorKind,
+    message: String,
+}
+
+impl io::Write for Unconnected {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(self.kind, self.message.clone()))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Builder {
+    #[cfg(unix)]
+    fn journal_stream_framing(&self, target: Target, is_pipe: bool) -> Option<Framing> {
+        if self.detect_journal_stream
+            && !is_pipe
+            && target == Target::Stderr
+            && self::journald::is_stderr_journal_stream()
+        {
+            Some(Framing::JournalStream)
+        } else {
+            None
         }
     }
+
+    #[cfg(not(unix))]
+    fn journal_stream_framing(&self, _: Target, _: bool) -> Option<Framing> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn syslog_debug(&self) -> Option<&Syslog> {
+        self.syslog.as_ref()
+    }
+
+    #[cfg(not(unix))]
+    fn syslog_debug(&self) -> Option<()> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn journald_debug(&self) -> Option<&Journald> {
+        self.journald.as_ref()
+    }
+
+    #[cfg(not(unix))]
+    fn journald_debug(&self) -> Option<()> {
+        None
+    }
 }
 
 impl Default for Builder {
@@ -153,7 +638,16 @@ impl fmt::Debug for Builder {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Logger")
             .field("target", &self.target)
+            .field("pipe", &self.pipe.as_ref().map(|_| "<pipe>"))
+            .field("split", &self.split)
+            .field("syslog", &self.syslog_debug())
+            .field("journald", &self.journald_debug())
+            .field("detect_journal_stream", &self.detect_journal_stream)
+            .field("tcp", &self.tcp)
+            .field("udp", &self.udp)
             .field("write_style", &self.write_style)
+            .field("background", &self.background)
+            .field("broken_pipe", &self.broken_pipe)
             .finish()
     }
 }
@@ -198,4 +692,59 @@ mod tests {
             assert_eq!(WriteStyle::Auto, parse_write_style(input));
         }
     }
+
+    #[test]
+    fn split_selects_writer_by_level() {
+        let writer = Builder::new()
+            .split(Level::Warn)
+            .write_style(WriteStyle::Never)
+            .build();
+
+        let stderr: *const Writer = &writer;
+
+        assert_eq!(stderr, writer.select(Level::Error) as *const Writer);
+        assert_eq!(stderr, writer.select(Level::Warn) as *const Writer);
+        assert_ne!(stderr, writer.select(Level::Info) as *const Writer);
+        assert_ne!(stderr, writer.select(Level::Trace) as *const Writer);
+    }
+
+    #[test]
+    fn unconnected_sockets_fall_back_to_target() {
+        let writer = Builder::new()
+            .udp(Udp::new("not an address"))
+            .is_test(true)
+            .build();
+
+        // Only terminal targets are captured for tests
+        assert!(writer.buffer_style().is_test);
+    }
+
+    #[test]
+    fn unconnected_sockets_report_write_errors() {
+        let writer = Builder::new()
+            .udp(Udp::new("not an address"))
+            .connect_error(ConnectErrorPolicy::Report)
+            .write_style(WriteStyle::Never)
+            .build();
+
+        let mut buf = writer.buffer();
+        buf.write(b"log\n").unwrap();
+
+        let record = Record::builder().args(format_args!("log")).build();
+        let error = writer.print(&buf, &record).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("failed to create UDP socket - "));
+    }
+
+    #[test]
+    fn split_is_cleared_by_target() {
+        let writer = Builder::new()
+            .split(Level::Warn)
+            .target(Target::Stdout)
+            .build();
+
+        assert!(writer.split.is_none());
+    }
 }
@@ -0,0 +1,468 @@
+/*
+This internal module contains the network writers.
+
+Formatted records are shipped to a collector over TCP, as a stream of records,
+or over UDP, as one datagram per record. The TCP writer keeps records in a
+bounded backlog while it's disconnected and reconnects with a backoff.
+*/
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+const DEFAULT_BACKLOG: usize = 1024;
+const DEFAULT_TIMEOUT_SECS: u64 = 1;
+const DEFAULT_MAX_BACKOFF_SECS: u64 = 30;
+const INITIAL_BACKOFF_MILLIS: u64 = 100;
+
+/// A collector to send log records to over TCP.
+///
+/// Formatted records are written to the connection as they are, so any format
+/// can be used. The default format writes one record per line.
+///
+/// The connection is opened when the first record is logged. If the collector
+/// can't be reached, records are kept in a bounded backlog and the connection is
+/// retried when later records are logged, waiting longer between each attempt.
+/// When the backlog is full the oldest records are dropped, and a line saying
+/// how many were dropped is sent once the connection is back.
+///
+/// If the connection fails partway through a record, only the part that wasn't
+/// written is kept, so no part of a record is sent twice. The collector sees that
+/// record split across the two connections.
+///
+/// Connecting and writing happen on the thread logging the record, so each
+/// attempt can block for up to the configured [`timeout`]. Combine this with
+/// [`Builder::write_in_background`] to keep that off the logging threads.
+///
+/// # Examples
+///
+/// Send log records to a local collector:
+///
+/// ```
+/// use env_logger::{Builder, Tcp};
+///
+/// let mut builder = Builder::new();
+///
+/// builder.tcp(Tcp::new("127.0.0.1:9000").backlog(4096));
+/// ```
+///
+/// [`timeout`]: #method.timeout
+/// [`Builder::write_in_background`]: ../struct.Builder.html#method.write_in_background
+#[derive(Clone, Debug)]
+pub struct Tcp {
+    addr: String,
+    backlog: usize,
+    timeout: Duration,
+    max_backoff: Duration,
+}
+
+impl Tcp {
+    /// Send records to the given address, like `127.0.0.1:9000`.
+    ///
+    /// The address is resolved each time the connection is opened.
+    pub fn new<A>(addr: A) -> Self
+    where
+        A: Into<String>,
+    {
+        Tcp {
+            addr: addr.into(),
+            backlog: DEFAULT_BACKLOG,
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            max_backoff: Duration::from_secs(DEFAULT_MAX_BACKOFF_SECS),
+        }
+    }
+
+    /// Set the most records to keep while disconnected.
+    ///
+    /// The default is 1024 records.
+    pub fn backlog(mut self, backlog: usize) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Set how long to wait when connecting or writing before giving up.
+    ///
+    /// The default is 1 second.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the longest time to wait between attempts to reconnect.
+    ///
+    /// The wait starts at 100 milliseconds and doubles after each failed attempt.
+    /// The default is 30 seconds.
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub(in crate::fmt::writer) fn connect(self) -> TcpWriter {
+        TcpWriter {
+            config: self,
+            stream: None,
+            backlog: VecDeque::new(),
+            dropped: 0,
+            backoff: Duration::from_millis(INITIAL_BACKOFF_MILLIS),
+            retry_at: None,
+        }
+    }
+}
+
+/// A collector to send log records to over UDP.
+///
+/// Each formatted record is sent as a single datagram, so records that don't fit
+/// in a datagram can't be sent. Records sent while the collector isn't listening
+/// are lost.
+///
+/// If the socket can't be created, records are written to the terminal target
+/// instead. See [`ConnectErrorPolicy`] for the other options.
+///
+/// # Examples
+///
+/// Send log records to a local collector:
+///
+/// ```
+/// use env_logger::{Builder, Udp};
+///
+/// let mut builder = Builder::new();
+///
+/// builder.udp(Udp::new("127.0.0.1:9000"));
+/// ```
+///
+/// [`ConnectErrorPolicy`]: ../enum.ConnectErrorPolicy.html
+#[derive(Clone, Debug)]
+pub struct Udp {
+    addr: String,
+}
+
+impl Udp {
+    /// Send records to the given address, like `127.0.0.1:9000`.
+    ///
+    /// The address is resolved when the logger is built.
+    pub fn new<A>(addr: A) -> Self
+    where
+        A: Into<String>,
+    {
+        Udp { addr: addr.into() }
+    }
+
+    pub(in crate::fmt::writer) fn connect(self) -> io::Result<UdpWriter> {
+        let addr = self
+            .addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| no_addresses(&self.addr))?;
+
+        let local: SocketAddr = if addr.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0; 8], 0).into()
+        };
+
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+
+        Ok(UdpWriter { socket })
+    }
+}
+
+pub(in crate::fmt::writer) struct TcpWriter {
+    config: Tcp,
+    stream: Option<TcpStream>,
+    backlog: VecDeque<Vec<u8>>,
+    dropped: u64,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl TcpWriter {
+    /// Whether there's a connection, opening one if it's time to retry.
+    fn try_connect(&mut self) -> bool {
+        if self.stream.is_some() {
+            return true;
+        }
+
+        if let Some(retry_at) = self.retry_at {
+            if Instant::now() < retry_at {
+                return false;
+            }
+        }
+
+        match self.open() {
+            Ok(stream) => {
+                self.stream = Some(stream);
+                self.backoff = Duration::from_millis(INITIAL_BACKOFF_MILLIS);
+                self.retry_at = None;
+                true
+            }
+            Err(_) => {
+                self.retry_at = Some(Instant::now() + self.backoff);
+                self.backoff = cmp::min(self.backoff * 2, self.config.max_backoff);
+                false
+            }
+        }
+    }
+
+    fn open(&self) -> io::Result<TcpStream> {
+        let mut last_err = None;
+
+        for addr in self.config.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.config.timeout) {
+                Ok(stream) => {
+                    stream.set_write_timeout(Some(self.config.timeout))?;
+                    return Ok(stream);
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| no_addresses(&self.config.addr)))
+    }
+
+    /// Drop the connection after a failed write.
+    ///
+    /// The next record retries straight away, since the collector may have restarted.
+    fn disconnect(&mut self) {
+        self.stream = None;
+        self.retry_at = None;
+    }
+
+    /// Send any records kept while disconnected, followed by `buf`.
+    ///
+    /// If the connection fails, the part of a kept record that wasn't written
+    /// stays in the backlog, and the number of bytes of `buf` that were written
+    /// is returned.
+    fn send(&mut self, buf: &[u8]) -> Result<(), usize> {
+        let stream = match self.stream {
+            Some(ref mut stream) => stream,
+            None => return Err(0),
+        };
+
+        // The notice is kept like a record, in case it can't be sent in full either
+        if self.dropped > 0 {
+            let notice = format!(
+                "[env_logger] dropped {} log records while disconnected from {}\n",
+                self.dropped, self.config.addr
+            );
+            self.backlog.push_front(notice.into_bytes());
+            self.dropped = 0;
+        }
+
+        while let Some(record) = self.backlog.front_mut() {
+            if let Err(written) = write_all(stream, record) {
+                record.drain(..written);
+                return Err(0);
+            }
+
+            self.backlog.pop_front();
+        }
+
+        write_all(stream, buf)
+    }
+
+    fn queue(&mut self, buf: &[u8]) {
+        if self.config.backlog == 0 {
+            self.dropped += 1;
+            return;
+        }
+
+        if self.backlog.len() >= self.config.backlog {
+            self.backlog.pop_front();
+            self.dropped += 1;
+        }
+
+        self.backlog.push_back(buf.to_vec());
+    }
+}
+
+impl io::Write for TcpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+
+        if self.try_connect() {
+            match self.send(buf) {
+                Ok(()) => return Ok(buf.len()),
+                Err(written) => {
+                    self.disconnect();
+                    rest = &buf[written..];
+                }
+            }
+        }
+
+        // The record is kept until it can be sent, so it isn't an error yet
+        self.queue(rest);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let failed = match self.stream {
+            Some(ref mut stream) => stream.flush().is_err(),
+            None => false,
+        };
+
+        if failed {
+            self.disconnect();
+        }
+
+        Ok(())
+    }
+}
+
+pub(in crate::fmt::writer) struct UdpWriter {
+    socket: UdpSocket,
+}
+
+impl io::Write for UdpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.send(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Write all of `buf`, returning how much of it was written if that fails.
+fn write_all(stream: &mut TcpStream, buf: &[u8]) -> Result<(), usize> {
+    let mut written = 0;
+
+    while written < buf.len() {
+        match stream.write(&buf[written..]) {
+            Ok(0) => return Err(written),
+            Ok(n) => written += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => return Err(written),
+        }
+    }
+
+    Ok(())
+}
+
+fn no_addresses(addr: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("`{}` didn't resolve to any addresses", addr),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    // Connecting fails straight away, without a port that another process could take
+    const UNREACHABLE: &str = "not an address";
+
+    fn read_lines(listener: &TcpListener, n: usize) -> Vec<String> {
+        let (stream, _) = listener.accept().expect("failed to accept connection");
+
+        BufReader::new(stream)
+            .lines()
+            .take(n)
+            .map(|line| line.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn tcp_sends_records() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let mut writer = Tcp::new(addr).connect();
+        writer.write_all(b"a\n").unwrap();
+        writer.write_all(b"b\n").unwrap();
+
+        assert_eq!(vec!["a", "b"], read_lines(&listener, 2));
+    }
+
+    #[test]
+    fn tcp_keeps_backlog_until_reconnected() {
+        let mut writer = Tcp::new(UNREACHABLE).backlog(2).connect();
+        for record in &[&b"a\n"[..], b"b\n", b"c\n"] {
+            writer.write_all(record).unwrap();
+        }
+
+        assert!(writer.stream.is_none());
+        assert!(writer.retry_at.is_some());
+        assert_eq!(2, writer.backlog.len());
+        assert_eq!(1, writer.dropped);
+
+        // The address is resolved again when reconnecting
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        writer.config.addr = addr.clone();
+        writer.retry_at = Some(Instant::now());
+        writer.write_all(b"d\n").unwrap();
+
+        assert_eq!(
+            vec![
+                format!(
+                    "[env_logger] dropped 1 log records while disconnected from {}",
+                    addr
+                ),
+                "b".to_owned(),
+                "c".to_owned(),
+                "d".to_owned(),
+            ],
+            read_lines(&listener, 4)
+        );
+        assert!(writer.backlog.is_empty());
+        assert_eq!(0, writer.dropped);
+    }
+
+    #[test]
+    fn tcp_waits_for_backoff() {
+        let mut writer = Tcp::new(UNREACHABLE)
+            .max_backoff(Duration::from_millis(250))
+            .connect();
+
+        writer.write_all(b"a\n").unwrap();
+        let retry_at = writer.retry_at.expect("missing retry");
+
+        // Still waiting, so no attempt is made
+        writer.write_all(b"b\n").unwrap();
+        assert_eq!(Some(retry_at), writer.retry_at);
+
+        for _ in 0..4 {
+            writer.retry_at = Some(Instant::now());
+            writer.write_all(b"c\n").unwrap();
+        }
+        assert_eq!(Duration::from_millis(250), writer.backoff);
+    }
+
+    #[test]
+    fn tcp_without_backlog_drops_records() {
+        let mut writer = Tcp::new(UNREACHABLE).backlog(0).connect();
+        writer.write_all(b"a\n").unwrap();
+
+        assert!(writer.backlog.is_empty());
+        assert_eq!(1, writer.dropped);
+    }
+
+    #[test]
+    fn udp_sends_a_datagram_per_record() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+
+        let mut writer = Udp::new(addr).connect().expect("failed to create socket");
+        writer.write_all(b"a message\n").unwrap();
+        writer.write_all(b"another\n").unwrap();
+
+        let mut buf = [0; 64];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&b"a message\n"[..], &buf[..len]);
+
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&b"another\n"[..], &buf[..len]);
+    }
+
+    #[test]
+    fn udp_fails_on_unresolved_address() {
+        assert!(Udp::new("not an address").connect().is_err());
+    }
+}
@@ -0,0 +1,397 @@
+/*
+This internal module contains the syslog writer.
+
+Records are formatted as usual and then framed as a syslog message before
+being sent to the local syslog daemon over a Unix datagram socket.
+*/
+
+use std::env;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use log::Level;
+
+use super::termcolor::Buffer;
+
+/// The default path of the local syslog socket.
+pub const DEFAULT_SYSLOG_PATH: &str = "/dev/log";
+
+/// A syslog facility, describing the kind of program logging a message.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Facility {
+    /// Messages from the kernel.
+    Kern,
+    /// Messages from user programs.
+    User,
+    /// Messages from the mail system.
+    Mail,
+    /// Messages from system daemons.
+    Daemon,
+    /// Security and authorization messages.
+    Auth,
+    /// Messages generated by the syslog daemon itself.
+    Syslog,
+    /// Messages from the line printer subsystem.
+    Lpr,
+    /// Messages from the network news subsystem.
+    News,
+    /// Messages from the UUCP subsystem.
+    Uucp,
+    /// Messages from the clock daemon.
+    Cron,
+    /// Private security and authorization messages.
+    AuthPriv,
+    /// Messages from the FTP daemon.
+    Ftp,
+    /// Reserved for local use.
+    Local0,
+    /// Reserved for local use.
+    Local1,
+    /// Reserved for local use.
+    Local2,
+    /// Reserved for local use.
+    Local3,
+    /// Reserved for local use.
+    Local4,
+    /// Reserved for local use.
+    Local5,
+    /// Reserved for local use.
+    Local6,
+    /// Reserved for local use.
+    Local7,
+}
+
+/// The framing used for syslog messages.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SyslogFormat {
+    /// The BSD syslog format described in [RFC 3164].
+    ///
+    /// Messages are written as `<PRI>APP-NAME[PROCID]: MSG`, leaving the daemon
+    /// to add the timestamp and hostname.
+    ///
+    /// [RFC 3164]: https://tools.ietf.org/html/rfc3164
+    Rfc3164,
+    /// The syslog protocol described in [RFC 5424].
+    ///
+    /// Messages are written as `<PRI>1 TIMESTAMP - APP-NAME PROCID - - MSG`.
+    /// The timestamp is only included when the `humantime` feature is enabled.
+    ///
+    /// [RFC 5424]: https://tools.ietf.org/html/rfc5424
+    Rfc5424,
+}
+
+/// The syslog daemon to send log records to.
+///
+/// Records are formatted using the logger's format and sent as the message of a
+/// syslog datagram. The level of each record is mapped to a syslog severity.
+/// Syslog daemons record the time of each message, so the timestamp in the
+/// default format can usually be turned off with [`Builder::format_timestamp`].
+///
+/// If the socket can't be created, records are written to the terminal target
+/// instead. See [`ConnectErrorPolicy`] for the other options.
+///
+/// # Examples
+///
+/// Send log records to the local syslog daemon:
+///
+/// ```
+/// use env_logger::{Builder, Facility, Syslog};
+///
+/// let mut builder = Builder::new();
+///
+/// builder
+///     .format_timestamp(None)
+///     .syslog(Syslog::new().facility(Facility::Daemon));
+/// ```
+///
+/// [`Builder::format_timestamp`]: ../struct.Builder.html#method.format_timestamp
+/// [`ConnectErrorPolicy`]: ../enum.ConnectErrorPolicy.html
+#[derive(Clone, Debug)]
+pub struct Syslog {
+    path: PathBuf,
+    facility: Facility,
+    format: SyslogFormat,
+    app_name: Option<String>,
+}
+
+impl Syslog {
+    /// Send records to the syslog daemon listening on `/dev/log`.
+    ///
+    /// Messages use the `user` facility and the [RFC 3164] format by default.
+    ///
+    /// [RFC 3164]: enum.SyslogFormat.html#variant.Rfc3164
+    pub fn new() -> Self {
+        Syslog {
+            path: DEFAULT_SYSLOG_PATH.into(),
+            facility: Facility::User,
+            format: SyslogFormat::Rfc3164,
+            app_name: None,
+        }
+    }
+
+    /// Set the path of the socket the syslog daemon is listening on.
+    pub fn path<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.path = path.as_ref().to_owned();
+        self
+    }
+
+    /// Set the facility messages are logged with.
+    pub fn facility(mut self, facility: Facility) -> Self {
+        self.facility = facility;
+        self
+    }
+
+    /// Set the framing used for messages.
+    pub fn format(mut self, format: SyslogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the name of the application sending messages.
+    ///
+    /// The name of the current executable is used by default.
+    pub fn app_name<S>(mut self, app_name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.app_name = Some(app_name.into());
+        self
+    }
+
+    pub(in crate::fmt::writer) fn connect(self) -> io::Result<(Framing, Datagram)> {
+        let app_name = self.app_name.unwrap_or_else(app_name);
+
+        let framing = Framing {
+            facility: self.facility,
+            format: self.format,
+            app_name,
+            pid: process::id(),
+        };
+
+        Ok((framing, Datagram::unbound(self.path)?))
+    }
+}
+
+impl Default for Syslog {
+    fn default() -> Self {
+        Syslog::new()
+    }
+}
+
+pub(in crate::fmt::writer) struct Framing {
+    facility: Facility,
+    format: SyslogFormat,
+    app_name: String,
+    pid: u32,
+}
+
+impl Framing {
+    pub(in crate::fmt::writer) fn frame(
+        &self,
+        level: Level,
+        message: &[u8],
+        out: &mut Buffer,
+    ) -> io::Result<()> {
+        let priority = self.facility.code() * 8 + severity(level);
+
+        let header = match self.format {
+            SyslogFormat::Rfc3164 => format!("<{}>{}[{}]: ", priority, self.app_name, self.pid),
+            SyslogFormat::Rfc5424 => format!(
+                "<{}>1 {} - {} {} - - ",
+                priority,
+                timestamp(),
+                self.app_name,
+                self.pid
+            ),
+        };
+
+        out.write(header.as_bytes())?;
+
+        // Each datagram is a single message so the trailing newline isn't needed
+        let message = match message.last() {
+            Some(b'\n') => &message[..message.len() - 1],
+            _ => message,
+        };
+        out.write(message)?;
+
+        Ok(())
+    }
+}
+
+/// A Unix datagram socket that sends each write as a single message.
+pub(in crate::fmt::writer) struct Datagram {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl Datagram {
+    pub(in crate::fmt::writer) fn unbound(path: PathBuf) -> io::Result<Self> {
+        Ok(Datagram {
+            socket: UnixDatagram::unbound()?,
+            path,
+        })
+    }
+}
+
+impl io::Write for Datagram {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // The socket isn't connected so records can still be sent
+        // if the daemon is restarted
+        self.socket.send_to(buf, &self.path)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Facility {
+    fn code(self) -> u8 {
+        match self {
+            Facility::Kern => 0,
+            Facility::User => 1,
+            Facility::Mail => 2,
+            Facility::Daemon => 3,
+            Facility::Auth => 4,
+            Facility::Syslog => 5,
+            Facility::Lpr => 6,
+            Facility::News => 7,
+            Facility::Uucp => 8,
+            Facility::Cron => 9,
+            Facility::AuthPriv => 10,
+            Facility::Ftp => 11,
+            Facility::Local0 => 16,
+            Facility::Local1 => 17,
+            Facility::Local2 => 18,
+            Facility::Local3 => 19,
+            Facility::Local4 => 20,
+            Facility::Local5 => 21,
+            Facility::Local6 => 22,
+            Facility::Local7 => 23,
+        }
+    }
+}
+
+/// The name of the current executable.
+pub(in crate::fmt::writer) fn app_name() -> String {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| {
+            exe.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "rust".into())
+}
+
+/// Map a log level to a syslog severity.
+pub(in crate::fmt::writer) fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+#[cfg(feature = "humantime")]
+fn timestamp() -> impl std::fmt::Display {
+    humantime::format_rfc3339_micros(std::time::SystemTime::now())
+}
+
+#[cfg(not(feature = "humantime"))]
+fn timestamp() -> impl std::fmt::Display {
+    "-"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use log::Record;
+
+    use crate::fmt::writer::Builder;
+
+    fn bind(name: &str) -> (UnixDatagram, PathBuf) {
+        let path = env::temp_dir().join(format!("env_logger-{}-{}.sock", name, process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let socket = UnixDatagram::bind(&path).expect("failed to bind socket");
+        (socket, path)
+    }
+
+    fn send(syslog: Syslog, level: Level, message: &str) {
+        let writer = Builder::new().syslog(syslog).build();
+
+        let mut buf = writer.buffer();
+        buf.write(message.as_bytes()).unwrap();
+
+        let record = Record::builder().level(level).build();
+        writer.print(&buf, &record).expect("failed to send record");
+    }
+
+    fn recv(socket: &UnixDatagram) -> String {
+        let mut buf = [0; 1024];
+        let len = socket.recv(&mut buf).expect("failed to receive record");
+
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn syslog_rfc3164() {
+        let (socket, path) = bind("rfc3164");
+
+        send(
+            Syslog::new()
+                .path(&path)
+                .facility(Facility::Local0)
+                .app_name("app"),
+            Level::Warn,
+            "a message\n",
+        );
+
+        assert_eq!(
+            format!("<132>app[{}]: a message", process::id()),
+            recv(&socket)
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn syslog_rfc5424() {
+        let (socket, path) = bind("rfc5424");
+
+        send(
+            Syslog::new()
+                .path(&path)
+                .format(SyslogFormat::Rfc5424)
+                .app_name("app"),
+            Level::Error,
+            "a message\n",
+        );
+
+        let received = recv(&socket);
+        assert!(received.starts_with("<11>1 "), "{}", received);
+        assert!(
+            received.ends_with(&format!(" - app {} - - a message", process::id())),
+            "{}",
+            received
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn severity_from_level() {
+        assert_eq!(3, severity(Level::Error));
+        assert_eq!(4, severity(Level::Warn));
+        assert_eq!(6, severity(Level::Info));
+        assert_eq!(7, severity(Level::Debug));
+        assert_eq!(7, severity(Level::Trace));
+    }
+}
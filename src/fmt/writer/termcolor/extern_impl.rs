@@ -3,10 +3,12 @@ use std::cell::RefCell;
 use std::fmt;
 use std::io::{self, Write};
 use std::rc::Rc;
+use std::sync::Arc;
 
 use log::Level;
 use termcolor::{self, ColorChoice, ColorSpec, WriteColor};
 
+use crate::fmt::writer::Pipe;
 use crate::fmt::{Formatter, Target, WriteStyle};
 
 pub(in crate::fmt::writer) mod glob {
@@ -71,9 +73,11 @@ impl Formatter {
 pub(in crate::fmt::writer) struct BufferWriter {
     inner: termcolor::BufferWriter,
     test_target: Option<Target>,
+    pipe: Option<Arc<Pipe>>,
 }
 
-pub(in crate::fmt) struct Buffer {
+#[derive(Clone)]
+pub(crate) struct Buffer {
     inner: termcolor::Buffer,
     test_target: Option<Target>,
 }
@@ -83,6 +87,7 @@ impl BufferWriter {
         BufferWriter {
             inner: termcolor::BufferWriter::stderr(write_style.into_color_choice()),
             test_target: if is_test { Some(Target::Stderr) } else { None },
+            pipe: None,
         }
     }
 
@@ -90,6 +95,17 @@ impl BufferWriter {
         BufferWriter {
             inner: termcolor::BufferWriter::stdout(write_style.into_color_choice()),
             test_target: if is_test { Some(Target::Stdout) } else { None },
+            pipe: None,
+        }
+    }
+
+    pub(in crate::fmt::writer) fn pipe(write_style: WriteStyle, pipe: Arc<Pipe>) -> Self {
+        BufferWriter {
+            // The inner writer is never printed to, but is still needed to create
+            // buffers that support the requested style
+            inner: termcolor::BufferWriter::stderr(write_style.into_color_choice()),
+            test_target: None,
+            pipe: Some(pipe),
         }
     }
 
@@ -101,7 +117,11 @@ impl BufferWriter {
     }
 
     pub(in crate::fmt::writer) fn print(&self, buf: &Buffer) -> io::Result<()> {
-        if let Some(target) = self.test_target {
+        if let Some(ref pipe) = self.pipe {
+            let mut pipe = pipe.lock().unwrap_or_else(|e| e.into_inner());
+
+            pipe.write_all(buf.bytes())
+        } else if let Some(target) = self.test_target {
             // This impl uses the `eprint` and `print` macros
             // instead of `termcolor`'s buffer.
             // This is so their output can be captured by `cargo test`
@@ -117,6 +137,13 @@ impl BufferWriter {
             self.inner.print(&buf.inner)
         }
     }
+    /// Flush the pipe, if there is one.
+    pub(in crate::fmt::writer) fn flush(&self) -> io::Result<()> {
+        match self.pipe {
+            Some(ref pipe) => pipe.lock().unwrap_or_else(|e| e.into_inner()).flush(),
+            None => Ok(()),
+        }
+    }
 }
 
 impl Buffer {
@@ -124,7 +151,7 @@ impl Buffer {
         self.inner.clear()
     }
 
-    pub(in crate::fmt) fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    pub(crate) fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.inner.write(buf)
     }
 
@@ -132,7 +159,7 @@ impl Buffer {
         self.inner.flush()
     }
 
-    pub(in crate::fmt) fn bytes(&self) -> &[u8] {
+    pub(crate) fn bytes(&self) -> &[u8] {
         self.inner.as_slice()
     }
 
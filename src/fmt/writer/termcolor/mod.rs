@@ -9,4 +9,5 @@ The terminal printing is shimmed when the `termcolor` crate is not available.
 #[cfg_attr(not(feature = "termcolor"), path = "shim_impl.rs")]
 mod imp;
 
+pub(crate) use self::imp::Buffer;
 pub(in crate::fmt) use self::imp::*;
@@ -1,25 +1,42 @@
-use std::io;
+use std::io::{self, Write};
+use std::sync::Arc;
 
+use crate::fmt::writer::Pipe;
 use crate::fmt::{Target, WriteStyle};
 
 pub(in crate::fmt::writer) mod glob {}
 
 pub(in crate::fmt::writer) struct BufferWriter {
     target: Target,
+    is_test: bool,
+    pipe: Option<Arc<Pipe>>,
 }
 
-pub(in crate::fmt) struct Buffer(Vec<u8>);
+#[derive(Clone)]
+pub(crate) struct Buffer(Vec<u8>);
 
 impl BufferWriter {
-    pub(in crate::fmt::writer) fn stderr(_is_test: bool, _write_style: WriteStyle) -> Self {
+    pub(in crate::fmt::writer) fn stderr(is_test: bool, _write_style: WriteStyle) -> Self {
         BufferWriter {
             target: Target::Stderr,
+            is_test,
+            pipe: None,
         }
     }
 
-    pub(in crate::fmt::writer) fn stdout(_is_test: bool, _write_style: WriteStyle) -> Self {
+    pub(in crate::fmt::writer) fn stdout(is_test: bool, _write_style: WriteStyle) -> Self {
         BufferWriter {
             target: Target::Stdout,
+            is_test,
+            pipe: None,
+        }
+    }
+
+    pub(in crate::fmt::writer) fn pipe(_write_style: WriteStyle, pipe: Arc<Pipe>) -> Self {
+        BufferWriter {
+            target: Target::Stderr,
+            is_test: false,
+            pipe: Some(pipe),
         }
     }
 
@@ -28,17 +45,38 @@ impl BufferWriter {
     }
 
     pub(in crate::fmt::writer) fn print(&self, buf: &Buffer) -> io::Result<()> {
-        // This impl uses the `eprint` and `print` macros
-        // instead of using the streams directly.
-        // This is so their output can be captured by `cargo test`
-        let log = String::from_utf8_lossy(&buf.0);
+        if let Some(ref pipe) = self.pipe {
+            let mut pipe = pipe.lock().unwrap_or_else(|e| e.into_inner());
 
-        match self.target {
-            Target::Stderr => eprint!("{}", log),
-            Target::Stdout => print!("{}", log),
+            return pipe.write_all(&buf.0);
         }
 
-        Ok(())
+        if self.is_test {
+            // This impl uses the `eprint` and `print` macros
+            // instead of using the streams directly.
+            // This is so their output can be captured by `cargo test`
+            let log = String::from_utf8_lossy(&buf.0);
+
+            match self.target {
+                Target::Stderr => eprint!("{}", log),
+                Target::Stdout => print!("{}", log),
+            }
+
+            return Ok(());
+        }
+
+        // Errors like a broken pipe are returned, rather than panicking like the macros
+        match self.target {
+            Target::Stderr => io::stderr().lock().write_all(&buf.0),
+            Target::Stdout => io::stdout().lock().write_all(&buf.0),
+        }
+    }
+    /// Flush the pipe, if there is one.
+    pub(in crate::fmt::writer) fn flush(&self) -> io::Result<()> {
+        match self.pipe {
+            Some(ref pipe) => pipe.lock().unwrap_or_else(|e| e.into_inner()).flush(),
+            None => Ok(()),
+        }
     }
 }
 
@@ -47,7 +85,7 @@ impl Buffer {
         self.0.clear();
     }
 
-    pub(in crate::fmt) fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    pub(crate) fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.0.extend(buf);
         Ok(buf.len())
     }
@@ -56,8 +94,7 @@ impl Buffer {
         Ok(())
     }
 
-    #[cfg(test)]
-    pub(in crate::fmt) fn bytes(&self) -> &[u8] {
+    pub(crate) fn bytes(&self) -> &[u8] {
         &self.0
     }
 }
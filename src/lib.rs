@@ -139,6 +139,42 @@
 //!   warn for hello. In both cases the log message must include a single digit
 //!   number followed by 'scopes'.
 //!
+//! ## Sampling
+//!
+//! A directive may keep only a random sample of the records it enables. The
+//! syntax is to append `@` followed by the fraction of records to keep, between
+//! `0` and `1`, with values outside that range clamped to it. Sampling only
+//! applies to `info`, `debug` and `trace` records, so warnings and errors are
+//! always logged. Each record is sampled separately.
+//!
+//! Some examples:
+//!
+//! * `hello=trace@0.01` turns on all logging for the 'hello' module, but only
+//!   logs about 1% of its records below `warn`.
+//! * `info,hello::hot_path=debug@0.1~100` logs about 10% of the debug and info
+//!   records in 'hello::hot_path', and at most 100 a second from each callsite.
+//!
+//! ## Rate limiting
+//!
+//! A directive may also limit how many records are logged from each callsite in
+//! its modules. The syntax is to append `~` followed by the number of records
+//! allowed each second. A different period can be given after a `:`, in `ms`,
+//! `s`, `m` or `h`. Once a callsite reaches its limit, its records are suppressed
+//! until the period ends, and then a warning says how many were suppressed.
+//!
+//! Some examples:
+//!
+//! * `hello=debug~10` allows at most 10 records a second from each callsite in
+//!   the 'hello' module.
+//! * `info,hello::poll~1:30s` allows at most 1 record every 30 seconds from each
+//!   callsite in the 'hello::poll' module.
+//!
+//! Limits can also be set with [`Builder::rate_limit`] and
+//! [`Builder::rate_limit_per_callsite`].
+//!
+//! [`Builder::rate_limit`]: struct.Builder.html#method.rate_limit
+//! [`Builder::rate_limit_per_callsite`]: struct.Builder.html#method.rate_limit_per_callsite
+//!
 //! ## Capturing logs in tests
 //!
 //! Records logged during `cargo test` will not be captured by the test harness by default.
@@ -167,6 +203,10 @@
 //! Enabling test capturing comes at the expense of color and other style support
 //! and may have performance implications.
 //!
+//! To assert on the records a test logged, start a [`capture`] on the test's thread.
+//! Tests that need their own filters or format can install a logger for just their
+//! thread with [`Builder::try_init_scoped`].
+//!
 //! ## Disabling colors
 //!
 //! Colors and other styles can be configured with the `RUST_LOG_STYLE`
@@ -231,6 +271,8 @@
 //! [log-crate-url]: https://docs.rs/log/
 //! [`Builder`]: struct.Builder.html
 //! [`Builder::is_test`]: struct.Builder.html#method.is_test
+//! [`Builder::try_init_scoped`]: struct.Builder.html#method.try_init_scoped
+//! [`capture`]: capture/index.html
 //! [`Env`]: struct.Env.html
 //! [`fmt`]: fmt/index.html
 
@@ -246,18 +288,40 @@
 #![cfg_attr(rustbuild, unstable(feature = "rustc_private", issue = "27812"))]
 #![deny(missing_debug_implementations, missing_docs, warnings)]
 
-use std::{borrow::Cow, cell::RefCell, env, io};
+use std::{borrow::Cow, cell::RefCell, env, io, path::Path, sync::Arc, time::Duration};
 
-use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
 
+pub mod capture;
+mod composite;
+pub mod config;
+mod dedup;
 pub mod filter;
 pub mod fmt;
-
+mod limit;
+mod panic;
+mod recorder;
+mod scope;
+mod stats;
+mod tick;
+mod write_error;
+
+pub use self::composite::Composite;
 pub use self::fmt::glob::*;
-
-use self::filter::Filter;
+pub use self::limit::RateLimit;
+pub use self::recorder::FlightRecorder;
+pub use self::scope::ScopeGuard;
+pub use self::stats::Stats;
+pub use self::write_error::WriteErrorPolicy;
+
+use self::dedup::Deduplicator;
+use self::filter::{Filter, Verdict};
 use self::fmt::writer::{self, Writer};
-use self::fmt::Formatter;
+use self::fmt::{FormatFn, Formatter};
+use self::limit::{Limiter, Summary};
+use self::recorder::{Recorded, Recorder};
+use self::stats::Counters;
+use self::write_error::WriteErrors;
 
 /// The default name for the environment variable to read filters from.
 pub const DEFAULT_FILTER_ENV: &'static str = "RUST_LOG";
@@ -265,6 +329,21 @@ pub const DEFAULT_FILTER_ENV: &'static str = "RUST_LOG";
 /// The default name for the environment variable to read style preferences from.
 pub const DEFAULT_WRITE_STYLE_ENV: &'static str = "RUST_LOG_STYLE";
 
+/// The default name for the environment variable to read the config file path from.
+pub const DEFAULT_CONFIG_ENV: &str = "RUST_LOG_CONFIG";
+
+/// The default name for the environment variable to read the target from.
+pub const DEFAULT_TARGET_ENV: &str = "RUST_LOG_TARGET";
+
+/// The default name for the environment variable to read the timestamp precision from.
+pub const DEFAULT_TIMESTAMP_ENV: &str = "RUST_LOG_TIMESTAMP";
+
+/// The default name for the environment variable to read default format options from.
+pub const DEFAULT_FORMAT_ENV: &str = "RUST_LOG_FORMAT";
+
+/// The default name for the environment variable to read the indentation from.
+pub const DEFAULT_INDENT_ENV: &str = "RUST_LOG_INDENT";
+
 /// Set of environment variables to configure from.
 ///
 /// # Default environment variables
@@ -273,12 +352,51 @@ pub const DEFAULT_WRITE_STYLE_ENV: &'static str = "RUST_LOG_STYLE";
 ///
 /// - `RUST_LOG`: the level filter
 /// - `RUST_LOG_STYLE`: whether or not to print styles with records.
+/// - `RUST_LOG_TARGET`: where to write records, either `stdout` or `stderr`.
+/// - `RUST_LOG_TIMESTAMP`: the precision of timestamps, either `secs`, `millis`,
+///   `micros` or `nanos`, or `off` to leave them out.
+/// - `RUST_LOG_FORMAT`: parts of the default format to turn on or off, as a comma
+///   separated list of `level`, `module_path` and `escape`. A part prefixed with
+///   `-` is turned off, like `-module_path`.
+/// - `RUST_LOG_INDENT`: the number of spaces to indent multiline records by, or
+///   `off` to not indent them.
+///
+/// Invalid values are ignored with a warning.
+///
+/// # Config files
+///
+/// A variable with the path of a [config file] to load is only read if it's set
+/// with [`Env::config`], or one of the methods like it, or named by
+/// [`Env::prefixed`]. Settings read from the environment take precedence over
+/// the config file.
+///
+/// ```
+/// use env_logger::{Builder, Env};
+///
+/// let env = Env::default().config("RUST_LOG_CONFIG");
+///
+/// let mut builder = Builder::from_env(env);
+/// ```
+///
+/// These sources can be configured using the builder methods on `Env`. Programs
+/// that need their own set of variables can use [`Env::prefixed`] to name them all
+/// after a common prefix.
 ///
-/// These sources can be configured using the builder methods on `Env`.
+/// [config file]: config/index.html
+/// [`Env::config`]: #method.config
+/// [`Env::prefixed`]: #method.prefixed
 #[derive(Debug)]
 pub struct Env<'a> {
     filter: Var<'a>,
     write_style: Var<'a>,
+    // Config files are only loaded when asked for
+    config: Option<Var<'a>>,
+    target: Var<'a>,
+    timestamp: Var<'a>,
+    format: Var<'a>,
+    indent: Var<'a>,
+    // Whether to read the default variables when the configured ones aren't set
+    fallback: bool,
 }
 
 #[derive(Debug)]
@@ -307,10 +425,24 @@ struct Var<'a> {
 /// [`Builder::try_init()`]: struct.Builder.html#method.try_init
 /// [`Builder`]: struct.Builder.html
 pub struct Logger {
-    writer: Writer,
+    sinks: Vec<Sink>,
     filter: Filter,
-    #[allow(unknown_lints, bare_trait_objects)]
-    format: Box<Fn(&mut Formatter, &Record) -> io::Result<()> + Sync + Send>,
+    formats: Vec<FormatFn>,
+    recorder: Option<Recorder>,
+    limiter: Option<Limiter>,
+    dedup: Option<Deduplicator>,
+    counters: Counters,
+    write_errors: WriteErrors,
+    log_panics: bool,
+    panic_backtrace: bool,
+}
+
+/// A writer for log records along with the level and format to use for it.
+struct Sink {
+    writer: Writer,
+    level: LevelFilter,
+    // An index into the logger's formats
+    format: usize,
 }
 
 /// `Builder` acts as builder for initializing a `Logger`.
@@ -339,12 +471,57 @@ pub struct Logger {
 ///     info!("info message");
 /// }
 /// ```
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Builder {
     filter: filter::Builder,
     writer: writer::Builder,
     format: fmt::Builder,
-    built: bool,
+    outputs: Vec<Output>,
+    recorder: Option<FlightRecorder>,
+    rate_limit: Option<RateLimit>,
+    rate_limit_per_callsite: Option<RateLimit>,
+    collapse_duplicates: Option<Duration>,
+    count_targets: Option<usize>,
+    write_error_policy: WriteErrorPolicy,
+    log_panics: bool,
+    panic_backtrace: bool,
+}
+
+/// An additional destination for log records.
+///
+/// Outputs are added to a logger with [`Builder::add_output`]. Records that match
+/// the logger's filters are written to its own target and to each of its outputs.
+/// Each output has its own target, minimum level and style, and can use its own format.
+///
+/// # Examples
+///
+/// Write everything at `debug` and above to a file, and only warnings and errors
+/// to `stderr`:
+///
+/// ```
+/// use std::fs::File;
+/// use log::LevelFilter;
+/// use env_logger::{Builder, Output, Target};
+///
+/// # fn run() -> std::io::Result<()> {
+/// let file = File::create("app.log")?;
+///
+/// let mut builder = Builder::new();
+///
+/// builder
+///     .filter_level(LevelFilter::Debug)
+///     .pipe(Box::new(file))
+///     .add_output(Output::new(Target::Stderr).filter_level(LevelFilter::Warn));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Builder::add_output`]: struct.Builder.html#method.add_output
+#[derive(Clone)]
+pub struct Output {
+    writer: writer::Builder,
+    level: LevelFilter,
+    format: Option<FormatFn>,
 }
 
 impl Builder {
@@ -414,14 +591,74 @@ impl Builder {
         let mut builder = Builder::new();
         let env = env.into();
 
-        if let Some(s) = env.get_filter() {
-            builder.parse_filters(&s);
+        // Settings from the environment take precedence over the config file
+        if let Some(path) = env.get_config() {
+            match Builder::from_config_file(&path) {
+                Ok(config) => builder = config,
+                Err(e) => eprintln!("warning: invalid log config '{}', ignoring it: {}", path, e),
+            }
+        }
+
+        if let Some((s, source)) = env.get_filter() {
+            builder.filter.parse_with_source(&s, source);
         }
 
         if let Some(s) = env.get_write_style() {
             builder.parse_write_style(&s);
         }
 
+        if let Some((s, var)) = env.get_target() {
+            match config::parse_target(&s) {
+                Some(target) => {
+                    builder.target(target);
+                }
+                None => invalid(var, &s, "`stdout` or `stderr`"),
+            }
+        }
+
+        if let Some((s, var)) = env.get_timestamp() {
+            match config::parse_timestamp(&s) {
+                Some(timestamp) => {
+                    builder.format_timestamp(timestamp);
+                }
+                None => invalid(var, &s, "`secs`, `millis`, `micros`, `nanos` or `off`"),
+            }
+        }
+
+        if let Some((s, var)) = env.get_format() {
+            for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+                let (name, on) = if part.starts_with('-') {
+                    (&part[1..], false)
+                } else {
+                    (part, true)
+                };
+
+                match name {
+                    "level" => builder.format_level(on),
+                    "module_path" => builder.format_module_path(on),
+                    "escape" => builder.format_escape(on),
+                    _ => {
+                        invalid(var, part, "`level`, `module_path` or `escape`");
+                        continue;
+                    }
+                };
+            }
+        }
+
+        if let Some((s, var)) = env.get_indent() {
+            match &*s {
+                "off" => {
+                    builder.format_indent(None);
+                }
+                s => match s.parse() {
+                    Ok(indent) => {
+                        builder.format_indent(Some(indent));
+                    }
+                    Err(_) => invalid(var, s, "a number of spaces or `off`"),
+                },
+            }
+        }
+
         builder
     }
 
@@ -447,6 +684,64 @@ impl Builder {
         Self::from_env(Env::default())
     }
 
+    /// Initializes the log builder from a config file.
+    ///
+    /// Config files use a subset of TOML. See the [`config`] module for the
+    /// settings a config file can have.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use env_logger::Builder;
+    ///
+    /// # fn run() -> Result<(), env_logger::config::Error> {
+    /// let mut builder = Builder::from_config_file("log.toml")?;
+    /// builder.init();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`config`]: config/index.html
+    pub fn from_config_file<P>(path: P) -> Result<Self, config::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut builder = Builder::new();
+        config::load_file(&mut builder, path.as_ref())?;
+
+        Ok(builder)
+    }
+
+    /// Initializes the log builder from the contents of a config file.
+    ///
+    /// See the [`config`] module for the settings a config file can have.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use env_logger::Builder;
+    ///
+    /// # fn run() -> Result<(), env_logger::config::Error> {
+    /// let mut builder = Builder::from_config_str(
+    ///     "filters = \"info\"\n\
+    ///      \n\
+    ///      [format]\n\
+    ///      timestamp = \"millis\"\n",
+    /// )?;
+    /// builder.init();
+    /// # Ok(())
+    /// # }
+    /// # run().unwrap();
+    /// ```
+    ///
+    /// [`config`]: config/index.html
+    pub fn from_config_str(config: &str) -> Result<Self, config::Error> {
+        let mut builder = Builder::new();
+        config::load(&mut builder, config)?;
+
+        Ok(builder)
+    }
+
     /// Sets the format function for formatting the log output.
     ///
     /// This function is called on each record logged and should format the
@@ -477,7 +772,7 @@ impl Builder {
     where
         F: Fn(&mut Formatter, &Record) -> io::Result<()> + Sync + Send,
     {
-        self.format.custom_format = Some(Box::new(format));
+        self.format.custom_format = Some(Arc::new(format));
         self
     }
 
@@ -508,6 +803,39 @@ impl Builder {
         self
     }
 
+    /// Configures the maximum number of bytes to write for a log message.
+    ///
+    /// Messages longer than the limit are cut short and end with a marker
+    /// like `… [truncated 48213 bytes]`. A value of `None` disables truncation.
+    ///
+    /// The limit applies to the default format. Custom formats can apply it
+    /// using [`Formatter::truncated`].
+    ///
+    /// [`Formatter::truncated`]: fmt/struct.Formatter.html#method.truncated
+    pub fn format_message_limit(&mut self, limit: Option<usize>) -> &mut Self {
+        self.format.format_message_limit = limit;
+        self
+    }
+
+    /// Whether or not to escape control characters in messages written by the default format.
+    ///
+    /// This is useful when messages contain untrusted input. Carriage returns,
+    /// ANSI escape sequences and other non-printable characters are written
+    /// using Rust's escape syntax, like `\r` or `\u{1b}`, so they can't corrupt
+    /// the terminal or spoof other records. Newlines are escaped too, unless
+    /// [`format_indent`] is set so the lines of a record stand apart. Tabs are
+    /// kept, and styles written by the format itself aren't affected.
+    ///
+    /// The [message limit] applies to the message before it's escaped, so escape
+    /// sequences are never cut short.
+    ///
+    /// [`format_indent`]: #method.format_indent
+    /// [message limit]: #method.format_message_limit
+    pub fn format_escape(&mut self, escape: bool) -> &mut Self {
+        self.format.format_escape = escape;
+        self
+    }
+
     /// Configures if timestamp should be included and in what precision.
     pub fn format_timestamp(&mut self, timestamp: Option<fmt::TimestampPrecision>) -> &mut Self {
         self.format.format_timestamp = timestamp;
@@ -600,6 +928,69 @@ impl Builder {
         self
     }
 
+    /// Adds filters to the logger that only keep a sample of their records.
+    ///
+    /// This is like [`filter`], but only a random fraction of the records below
+    /// `warn` are logged, given by `rate` between `0.0` and `1.0`. Warnings and
+    /// errors are always logged. A `rate` outside that range is clamped to it.
+    ///
+    /// Records that aren't sampled are never kept by a [`FlightRecorder`].
+    ///
+    /// # Examples
+    ///
+    /// Only include 1% of the trace records for logs in `path::to::module`:
+    ///
+    /// ```
+    /// use log::LevelFilter;
+    /// use env_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder.filter_sampled(Some("path::to::module"), LevelFilter::Trace, 0.01);
+    /// ```
+    ///
+    /// [`filter`]: #method.filter
+    /// [`FlightRecorder`]: struct.FlightRecorder.html
+    pub fn filter_sampled(
+        &mut self,
+        module: Option<&str>,
+        level: LevelFilter,
+        rate: f64,
+    ) -> &mut Self {
+        self.filter.filter_sampled(module, level, rate);
+        self
+    }
+
+    /// Sets the level for modules without a filter from a verbosity, like the
+    /// number of `-v` and `-q` flags given to a command line program.
+    ///
+    /// The level is `default` made more verbose by `delta` levels, or less verbose
+    /// if `delta` is negative. It replaces the level of a filter without a module
+    /// set in code, but a level set in `RUST_LOG`, like `RUST_LOG=debug`, and
+    /// filters for specific modules still win. See [`filter::Builder::verbosity`]
+    /// for the exact rules.
+    ///
+    /// # Examples
+    ///
+    /// Log warnings by default, with each `-v` logging one more level and each `-q`
+    /// one less:
+    ///
+    /// ```
+    /// use log::LevelFilter;
+    /// use env_logger::Builder;
+    ///
+    /// # let (verbose, quiet) = (2, 0);
+    /// let mut builder = Builder::from_default_env();
+    ///
+    /// builder.verbosity(LevelFilter::Warn, verbose - quiet);
+    /// ```
+    ///
+    /// [`filter::Builder::verbosity`]: filter/struct.Builder.html#method.verbosity
+    pub fn verbosity(&mut self, default: LevelFilter, delta: i32) -> &mut Self {
+        self.filter.verbosity(default, delta);
+        self
+    }
+
     /// Parses the directives string in the same form as the `RUST_LOG`
     /// environment variable.
     ///
@@ -609,6 +1000,34 @@ impl Builder {
         self
     }
 
+    /// Parses directives that override the filters from code and the environment.
+    ///
+    /// Directives for the same module from [`parse_filters`], [`filter`] or `RUST_LOG`
+    /// are replaced, regardless of the order they're added in. This is useful for
+    /// filters set while the program is running, like from command line flags.
+    /// See [`filter::Source`] for how directives are merged.
+    ///
+    /// # Examples
+    ///
+    /// Turn on trace logging for `my_app::db`, even if `RUST_LOG` sets a level for it:
+    ///
+    /// ```
+    /// use env_logger::Builder;
+    ///
+    /// let mut builder = Builder::from_default_env();
+    ///
+    /// builder.parse_overrides("my_app::db=trace");
+    /// ```
+    ///
+    /// [`parse_filters`]: #method.parse_filters
+    /// [`filter`]: #method.filter
+    /// [`filter::Source`]: filter/enum.Source.html
+    pub fn parse_overrides(&mut self, filters: &str) -> &mut Self {
+        self.filter
+            .parse_with_source(filters, filter::Source::Override);
+        self
+    }
+
     /// Sets the target for the log output.
     ///
     /// Env logger can log to either stdout or stderr. The default is stderr.
@@ -629,317 +1048,1524 @@ impl Builder {
         self
     }
 
-    /// Sets whether or not styles will be written.
+    /// Sets an arbitrary writer, like a file, as the target for the log output.
     ///
-    /// This can be useful in environments that don't support control characters
-    /// for setting colors.
+    /// Styles are only written to the pipe if the write style is [`WriteStyle::Always`].
     ///
     /// # Examples
     ///
-    /// Never attempt to write styles:
+    /// Write log messages to a file:
     ///
     /// ```
-    /// use env_logger::{Builder, WriteStyle};
+    /// use std::fs::File;
+    /// use env_logger::Builder;
     ///
+    /// # fn run() -> std::io::Result<()> {
     /// let mut builder = Builder::new();
     ///
-    /// builder.write_style(WriteStyle::Never);
+    /// builder.pipe(Box::new(File::create("app.log")?));
+    /// # Ok(())
+    /// # }
     /// ```
-    pub fn write_style(&mut self, write_style: fmt::WriteStyle) -> &mut Self {
-        self.writer.write_style(write_style);
+    ///
+    /// [`WriteStyle::Always`]: enum.WriteStyle.html#variant.Always
+    pub fn pipe(&mut self, pipe: Box<dyn io::Write + Send>) -> &mut Self {
+        self.writer.pipe(pipe);
         self
     }
 
-    /// Parses whether or not to write styles in the same form as the `RUST_LOG_STYLE`
-    /// environment variable.
+    /// Sends log records to a syslog daemon instead of a terminal.
     ///
-    /// See the module documentation for more details.
-    pub fn parse_write_style(&mut self, write_style: &str) -> &mut Self {
-        self.writer.parse_write_style(write_style);
+    /// See [`Syslog`] for more details.
+    ///
+    /// [`Syslog`]: fmt/struct.Syslog.html
+    #[cfg(unix)]
+    pub fn syslog(&mut self, syslog: fmt::Syslog) -> &mut Self {
+        self.writer.syslog(syslog);
         self
     }
 
-    /// Sets whether or not the logger will be used in unit tests.
+    /// Sends log records to the systemd journal instead of a terminal.
     ///
-    /// If `is_test` is `true` then the logger will allow the testing framework to
-    /// capture log records rather than printing them to the terminal directly.
-    pub fn is_test(&mut self, is_test: bool) -> &mut Self {
-        self.writer.is_test(is_test);
+    /// See [`Journald`] for more details.
+    ///
+    /// [`Journald`]: fmt/struct.Journald.html
+    #[cfg(unix)]
+    pub fn journald(&mut self, journald: fmt::Journald) -> &mut Self {
+        self.writer.journald(journald);
         self
     }
 
-    /// Initializes the global logger with the built env logger.
+    /// Sends log records to a collector over TCP instead of a terminal.
     ///
-    /// This should be called early in the execution of a Rust program. Any log
-    /// events that occur before initialization will be ignored.
-    ///
-    /// # Errors
+    /// See [`Tcp`] for more details.
     ///
-    /// This function will fail if it is called more than once, or if another
-    /// library has already initialized a global logger.
-    pub fn try_init(&mut self) -> Result<(), SetLoggerError> {
-        let logger = self.build();
-
-        let max_level = logger.filter();
-        let r = log::set_boxed_logger(Box::new(logger));
-
-        if r.is_ok() {
-            log::set_max_level(max_level);
-        }
-
-        r
+    /// [`Tcp`]: fmt/struct.Tcp.html
+    pub fn tcp(&mut self, tcp: fmt::Tcp) -> &mut Self {
+        self.writer.tcp(tcp);
+        self
     }
 
-    /// Initializes the global logger with the built env logger.
-    ///
-    /// This should be called early in the execution of a Rust program. Any log
-    /// events that occur before initialization will be ignored.
+    /// Sends log records to a collector over UDP instead of a terminal.
     ///
-    /// # Panics
+    /// See [`Udp`] for more details.
     ///
-    /// This function will panic if it is called more than once, or if another
-    /// library has already initialized a global logger.
-    pub fn init(&mut self) {
-        self.try_init()
-            .expect("Builder::init should not be called after logger initialized");
+    /// [`Udp`]: fmt/struct.Udp.html
+    pub fn udp(&mut self, udp: fmt::Udp) -> &mut Self {
+        self.writer.udp(udp);
+        self
     }
 
-    /// Build an env logger.
+    /// Prefixes lines with their priority when `stderr` is connected to the journal.
     ///
-    /// The returned logger implements the `Log` trait and can be installed manually
-    /// or nested within another logger.
-    pub fn build(&mut self) -> Logger {
-        assert!(!self.built, "attempt to re-use consumed builder");
-        self.built = true;
-
-        Logger {
-            writer: self.writer.build(),
-            filter: self.filter.build(),
-            format: self.format.build(),
-        }
-    }
-}
-
-impl Logger {
-    /// Creates the logger from the environment.
+    /// systemd sets the `JOURNAL_STREAM` environment variable for services whose
+    /// output is connected to the journal. When it matches `stderr`, each line is
+    /// prefixed with a `<N>` marker so the journal records the level of the record.
+    /// This only applies when writing to `stderr`.
     ///
-    /// The variables used to read configuration from can be tweaked before
-    /// passing in.
+    /// This is disabled by default.
     ///
     /// # Examples
     ///
-    /// Create a logger reading the log filter from an environment variable
-    /// called `MY_LOG`:
-    ///
-    /// ```
-    /// use env_logger::Logger;
-    ///
-    /// let logger = Logger::from_env("MY_LOG");
     /// ```
+    /// use env_logger::Builder;
     ///
-    /// Create a logger using the `MY_LOG` variable for filtering and
-    /// `MY_LOG_STYLE` for whether or not to write styles:
+    /// let mut builder = Builder::new();
     ///
+    /// builder.detect_journal_stream(true);
     /// ```
-    /// use env_logger::{Logger, Env};
+    pub fn detect_journal_stream(&mut self, detect: bool) -> &mut Self {
+        self.writer.detect_journal_stream(detect);
+        self
+    }
+
+    /// Adds an output that log records are also written to.
     ///
-    /// let env = Env::new().filter_or("MY_LOG", "info").write_style_or("MY_LOG_STYLE", "always");
+    /// See [`Output`] for more details.
     ///
-    /// let logger = Logger::from_env(env);
-    /// ```
-    pub fn from_env<'a, E>(env: E) -> Self
-    where
-        E: Into<Env<'a>>,
-    {
-        Builder::from_env(env).build()
+    /// [`Output`]: struct.Output.html
+    pub fn add_output(&mut self, output: Output) -> &mut Self {
+        self.outputs.push(output);
+        self
     }
 
-    /// Creates the logger from the environment using default variable names.
+    /// Keeps recent records that were filtered out, to print when an error is logged.
     ///
-    /// This method is a convenient way to call `from_env(Env::default())` without
-    /// having to use the `Env` type explicitly. The logger will use the
-    /// [default environment variables].
+    /// See [`FlightRecorder`] for more details.
     ///
-    /// # Examples
+    /// [`FlightRecorder`]: struct.FlightRecorder.html
+    pub fn flight_recorder(&mut self, recorder: FlightRecorder) -> &mut Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Limits how many records are logged in total.
     ///
-    /// Creates a logger using the default environment variables:
+    /// See [`RateLimit`] for more details.
     ///
-    /// ```
+    /// [`RateLimit`]: struct.RateLimit.html
+    pub fn rate_limit(&mut self, rate_limit: RateLimit) -> &mut Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Limits how many records are logged from each callsite.
+    ///
+    /// Limits set for a module in the filter directives take precedence over this one.
+    /// See [`RateLimit`] for more details.
+    ///
+    /// [`RateLimit`]: struct.RateLimit.html
+    pub fn rate_limit_per_callsite(&mut self, rate_limit: RateLimit) -> &mut Self {
+        self.rate_limit_per_callsite = Some(rate_limit);
+        self
+    }
+
+    /// Collapses consecutive duplicate records into a single repeat count.
+    ///
+    /// Records with the same level, target and message as the record printed just
+    /// before them are counted instead of printed, regardless of the format. The
+    /// count is printed as `last message repeated N times`, with the same level and
+    /// target as the repeated record, once a different record is logged. A long run
+    /// of duplicates also has its count printed once `timeout` has passed since the
+    /// first duplicate that wasn't counted yet, and any count still pending is printed
+    /// when the logger is flushed.
+    ///
+    /// An installed logger prints counts within about a second of their timeout, even
+    /// if nothing else is logged. A logger that's only built prints them when the next
+    /// duplicate arrives.
+    ///
+    /// Records suppressed by rate limits don't count as duplicates, and don't end a
+    /// run of them.
+    ///
+    /// This is disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use env_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder.collapse_duplicates(Duration::from_secs(30));
+    /// ```
+    pub fn collapse_duplicates(&mut self, timeout: Duration) -> &mut Self {
+        self.collapse_duplicates = Some(timeout);
+        self
+    }
+
+    /// Count the records printed for each target.
+    ///
+    /// The `top` targets with the most records are included in the logger's
+    /// [`Stats`]. Counting targets takes a lock for each record printed, so
+    /// this is disabled by default. At most ten times `top` targets are
+    /// counted, so memory use stays bounded however many targets there are.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use env_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder.count_targets(10);
+    /// ```
+    ///
+    /// [`Stats`]: struct.Stats.html
+    pub fn count_targets(&mut self, top: usize) -> &mut Self {
+        self.count_targets = Some(top);
+        self
+    }
+
+    /// Sets what to do when a record can't be formatted or written.
+    ///
+    /// Errors are discarded by default. See [`WriteErrorPolicy`] for the other
+    /// options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use env_logger::{Builder, WriteErrorPolicy};
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder.write_error_policy(WriteErrorPolicy::ReportOnce);
+    /// ```
+    ///
+    /// [`WriteErrorPolicy`]: enum.WriteErrorPolicy.html
+    pub fn write_error_policy(&mut self, policy: WriteErrorPolicy) -> &mut Self {
+        self.write_error_policy = policy;
+        self
+    }
+
+    /// Whether or not to log panics through the logger.
+    ///
+    /// When the logger is initialized, a panic hook is installed that logs the
+    /// panic's message, location and thread name as an error, with the `panic`
+    /// target. Panics are logged regardless of the logger's filters, and the
+    /// logger is flushed afterwards.
+    ///
+    /// The hook that was installed before is still called, so with the default hook
+    /// panics are also printed to `stderr`.
+    ///
+    /// This is disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use env_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder.log_panics(true);
+    /// ```
+    pub fn log_panics(&mut self, log_panics: bool) -> &mut Self {
+        self.log_panics = log_panics;
+        self
+    }
+
+    /// Whether or not to include a backtrace when logging panics.
+    ///
+    /// Backtraces are captured regardless of the `RUST_BACKTRACE` environment
+    /// variable. This only applies when [`log_panics`] is enabled.
+    ///
+    /// This is disabled by default. It's only available with the `backtrace`
+    /// feature, which needs Rust 1.65 or later.
+    ///
+    /// [`log_panics`]: #method.log_panics
+    #[cfg(feature = "backtrace")]
+    pub fn panic_backtrace(&mut self, panic_backtrace: bool) -> &mut Self {
+        self.panic_backtrace = panic_backtrace;
+        self
+    }
+
+    /// Splits the log output between `stderr` and `stdout` by level.
+    ///
+    /// Records at the given level or more severe are written to `stderr`, and less
+    /// severe records are written to `stdout`. This keeps the output of command line
+    /// tools clean when it's piped to other programs. With [`WriteStyle::Auto`], each
+    /// stream only gets styles if it's attached to a terminal.
+    ///
+    /// Calling [`target`] or [`pipe`] afterwards replaces the split.
+    ///
+    /// # Examples
+    ///
+    /// Write warnings and errors to `stderr` and everything else to `stdout`:
+    ///
+    /// ```
+    /// use log::Level;
+    /// use env_logger::Builder;
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder.split_target(Level::Warn);
+    /// ```
+    ///
+    /// [`WriteStyle::Auto`]: enum.WriteStyle.html#variant.Auto
+    /// [`target`]: #method.target
+    /// [`pipe`]: #method.pipe
+    pub fn split_target(&mut self, level: Level) -> &mut Self {
+        self.writer.split(level);
+        self
+    }
+
+    /// Sets what to do when the target fails with a broken pipe.
+    ///
+    /// By default a broken pipe is handled like any other write error, and every
+    /// record after it is still written to the target. See [`BrokenPipePolicy`]
+    /// for the other options.
+    ///
+    /// # Examples
+    ///
+    /// Exit quietly when a program's output is piped into `head`:
+    ///
+    /// ```
+    /// use env_logger::{Builder, BrokenPipePolicy, Target};
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder
+    ///     .target(Target::Stdout)
+    ///     .broken_pipe_policy(BrokenPipePolicy::Exit);
+    /// ```
+    ///
+    /// [`BrokenPipePolicy`]: enum.BrokenPipePolicy.html
+    pub fn broken_pipe_policy(&mut self, policy: fmt::BrokenPipePolicy) -> &mut Self {
+        self.writer.broken_pipe(policy);
+        self
+    }
+
+    /// Sets what to do when a socket for the target can't be created.
+    ///
+    /// By default records are written to the terminal target instead, after a
+    /// warning the first time. See [`ConnectErrorPolicy`] for the other options.
+    ///
+    /// # Examples
+    ///
+    /// Handle records as write errors when the UDP socket can't be created, and
+    /// report the first one:
+    ///
+    /// ```
+    /// use env_logger::{Builder, ConnectErrorPolicy, Udp, WriteErrorPolicy};
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder
+    ///     .udp(Udp::new("logs.example.com:5140"))
+    ///     .connect_error_policy(ConnectErrorPolicy::Report)
+    ///     .write_error_policy(WriteErrorPolicy::ReportOnce);
+    /// ```
+    ///
+    /// [`ConnectErrorPolicy`]: enum.ConnectErrorPolicy.html
+    pub fn connect_error_policy(&mut self, policy: fmt::ConnectErrorPolicy) -> &mut Self {
+        self.writer.connect_error(policy);
+        self
+    }
+
+    /// Sets whether or not styles will be written.
+    ///
+    /// This can be useful in environments that don't support control characters
+    /// for setting colors.
+    ///
+    /// # Examples
+    ///
+    /// Never attempt to write styles:
+    ///
+    /// ```
+    /// use env_logger::{Builder, WriteStyle};
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder.write_style(WriteStyle::Never);
+    /// ```
+    pub fn write_style(&mut self, write_style: fmt::WriteStyle) -> &mut Self {
+        self.writer.write_style(write_style);
+        self
+    }
+
+    /// Parses whether or not to write styles in the same form as the `RUST_LOG_STYLE`
+    /// environment variable.
+    ///
+    /// See the module documentation for more details.
+    pub fn parse_write_style(&mut self, write_style: &str) -> &mut Self {
+        self.writer.parse_write_style(write_style);
+        self
+    }
+
+    /// Writes log records on a dedicated background thread.
+    ///
+    /// Formatted records are sent over a queue holding up to `capacity` records,
+    /// so logging doesn't block on a slow terminal or pipe. The `policy` decides
+    /// what happens when the queue is full. If records are dropped, a line with
+    /// the number of dropped records is written before the next record.
+    ///
+    /// Records still in the queue when the process exits may be lost. Call
+    /// [`log::logger().flush()`] before exiting to wait for them to be written.
+    /// Records written on the background thread can't be captured by `cargo test`.
+    ///
+    /// # Examples
+    ///
+    /// Drop the oldest records instead of blocking when the writer falls behind:
+    ///
+    /// ```
+    /// use env_logger::{Builder, QueueFullPolicy};
+    ///
+    /// let mut builder = Builder::new();
+    ///
+    /// builder.write_in_background(1024, QueueFullPolicy::DropOldest);
+    /// ```
+    ///
+    /// [`log::logger().flush()`]: https://docs.rs/log/0.4/log/fn.logger.html
+    pub fn write_in_background(
+        &mut self,
+        capacity: usize,
+        policy: fmt::QueueFullPolicy,
+    ) -> &mut Self {
+        self.writer.background(capacity, policy);
+        self
+    }
+
+    /// Sets whether or not the logger will be used in unit tests.
+    ///
+    /// If `is_test` is `true` then the logger will allow the testing framework to
+    /// capture log records rather than printing them to the terminal directly.
+    pub fn is_test(&mut self, is_test: bool) -> &mut Self {
+        self.writer.is_test(is_test);
+        self
+    }
+
+    /// Initializes the global logger with the built env logger.
+    ///
+    /// This should be called early in the execution of a Rust program. Any log
+    /// events that occur before initialization will be ignored.
+    ///
+    /// # Errors
+    ///
+    /// This function will fail if it is called more than once, or if another
+    /// library has already initialized a global logger.
+    pub fn try_init(&mut self) -> Result<(), SetLoggerError> {
+        let logger = self.build();
+
+        if logger.handles_panics() {
+            panic::install_hook();
+        }
+
+        scope::set_global(logger.into())
+    }
+
+    /// Initializes the global logger with the built env logger.
+    ///
+    /// This should be called early in the execution of a Rust program. Any log
+    /// events that occur before initialization will be ignored.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if it is called more than once, or if another
+    /// library has already initialized a global logger.
+    pub fn init(&mut self) {
+        self.try_init()
+            .expect("Builder::init should not be called after logger initialized");
+    }
+
+    /// Installs the built env logger for the current thread until the returned
+    /// guard is dropped.
+    ///
+    /// Records logged on the current thread are sent to this logger instead of the
+    /// global one, so each test in a binary can use its own filters and format.
+    /// Records logged on other threads, including threads spawned while the guard
+    /// is alive, still go to the global logger set by [`try_init`]. Scoped loggers
+    /// can be nested, and dropping a guard restores the logger used before it.
+    ///
+    /// Because the `log` crate's maximum level is shared by every thread, it's
+    /// raised to this logger's level if that's more verbose, and it isn't lowered
+    /// again until every scoped logger is dropped. Until then, records on other
+    /// threads that are more verbose than the global logger's level aren't
+    /// discarded by the logging macros, and cost a call to the global logger's
+    /// filters instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use log::{debug, LevelFilter};
+    /// use env_logger::Builder;
+    ///
+    /// let _guard = Builder::new()
+    ///     .filter_level(LevelFilter::Debug)
+    ///     .is_test(true)
+    ///     .try_init_scoped()
+    ///     .unwrap();
+    ///
+    /// debug!("only this thread logs at debug level");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This function will fail if another library has already initialized a
+    /// global logger.
+    ///
+    /// [`try_init`]: #method.try_init
+    pub fn try_init_scoped(&mut self) -> Result<ScopeGuard, SetLoggerError> {
+        let logger = self.build();
+
+        if logger.handles_panics() {
+            panic::install_hook();
+        }
+
+        scope::scope(logger.into())
+    }
+
+    /// Installs the built env logger for the current thread until the returned
+    /// guard is dropped.
+    ///
+    /// See [`try_init_scoped`] for more details.
+    ///
+    /// # Panics
+    ///
+    /// This function will panic if another library has already initialized a
+    /// global logger.
+    ///
+    /// [`try_init_scoped`]: #method.try_init_scoped
+    pub fn init_scoped(&mut self) -> ScopeGuard {
+        self.try_init_scoped()
+            .expect("Builder::init_scoped should not be called after another logger is initialized")
+    }
+
+    /// Build an env logger.
+    ///
+    /// The returned logger implements the `Log` trait and can be installed manually
+    /// or nested within another logger.
+    ///
+    /// The builder isn't consumed, so it can be used as a template to build any
+    /// number of loggers. Loggers built from the same builder share any pipes it
+    /// writes to, but open their own sockets.
+    ///
+    /// # Examples
+    ///
+    /// Build a logger for each subsystem from a shared template:
+    ///
+    /// ```
+    /// use log::LevelFilter;
+    /// use env_logger::Builder;
+    ///
+    /// let mut template = Builder::new();
+    /// template.filter_level(LevelFilter::Info);
+    ///
+    /// let network = template.clone().filter_module("net", LevelFilter::Trace).build();
+    /// let storage = template.build();
+    /// ```
+    pub fn build(&self) -> Logger {
+        let message_limit = self.format.format_message_limit;
+
+        let mut formats = vec![self.format.build()];
+        let mut sinks = vec![Sink {
+            writer: self.writer.build(),
+            level: LevelFilter::max(),
+            format: 0,
+        }];
+
+        for output in &self.outputs {
+            let format = match output.format {
+                Some(ref format) => {
+                    let builder = fmt::Builder {
+                        format_message_limit: message_limit,
+                        custom_format: Some(format.clone()),
+                        ..Default::default()
+                    };
+
+                    formats.push(builder.build());
+                    formats.len() - 1
+                }
+                None => 0,
+            };
+
+            sinks.push(Sink {
+                writer: output.writer.build(),
+                level: output.level,
+                format,
+            });
+        }
+
+        let filter = self.filter.build();
+
+        let limiter = if self.rate_limit.is_some()
+            || self.rate_limit_per_callsite.is_some()
+            || filter.has_rate_limits()
+        {
+            Some(Limiter::new(self.rate_limit, self.rate_limit_per_callsite))
+        } else {
+            None
+        };
+
+        Logger {
+            sinks,
+            filter,
+            formats,
+            recorder: self.recorder.clone().map(Recorder::new),
+            limiter,
+            dedup: self.collapse_duplicates.map(Deduplicator::new),
+            counters: Counters::new(self.count_targets),
+            write_errors: WriteErrors::new(self.write_error_policy.clone()),
+            log_panics: self.log_panics,
+            panic_backtrace: self.panic_backtrace,
+        }
+    }
+}
+
+impl Output {
+    /// Creates an output that writes to the given terminal target.
+    ///
+    /// The output accepts records at any level the logger's filters allow.
+    pub fn new(target: fmt::Target) -> Self {
+        let mut writer = writer::Builder::new();
+        writer.target(target);
+
+        Output {
+            writer,
+            level: LevelFilter::max(),
+            format: None,
+        }
+    }
+
+    /// Creates an output that writes to an arbitrary writer, like a file.
+    ///
+    /// Styles are only written to the pipe if the write style is [`WriteStyle::Always`].
+    ///
+    /// [`WriteStyle::Always`]: enum.WriteStyle.html#variant.Always
+    pub fn pipe(pipe: Box<dyn io::Write + Send>) -> Self {
+        let mut writer = writer::Builder::new();
+        writer.pipe(pipe);
+
+        Output {
+            writer,
+            level: LevelFilter::max(),
+            format: None,
+        }
+    }
+
+    /// Creates an output that sends records to a syslog daemon.
+    ///
+    /// See [`Syslog`] for more details.
+    ///
+    /// [`Syslog`]: fmt/struct.Syslog.html
+    #[cfg(unix)]
+    pub fn syslog(syslog: fmt::Syslog) -> Self {
+        let mut writer = writer::Builder::new();
+        writer.syslog(syslog);
+
+        Output {
+            writer,
+            level: LevelFilter::max(),
+            format: None,
+        }
+    }
+
+    /// Creates an output that sends records to the systemd journal.
+    ///
+    /// See [`Journald`] for more details.
+    ///
+    /// [`Journald`]: fmt/struct.Journald.html
+    #[cfg(unix)]
+    pub fn journald(journald: fmt::Journald) -> Self {
+        let mut writer = writer::Builder::new();
+        writer.journald(journald);
+
+        Output {
+            writer,
+            level: LevelFilter::max(),
+            format: None,
+        }
+    }
+
+    /// Creates an output that sends records to a collector over TCP.
+    ///
+    /// See [`Tcp`] for more details.
+    ///
+    /// [`Tcp`]: fmt/struct.Tcp.html
+    pub fn tcp(tcp: fmt::Tcp) -> Self {
+        let mut writer = writer::Builder::new();
+        writer.tcp(tcp);
+
+        Output {
+            writer,
+            level: LevelFilter::max(),
+            format: None,
+        }
+    }
+
+    /// Creates an output that sends records to a collector over UDP.
+    ///
+    /// See [`Udp`] for more details.
+    ///
+    /// [`Udp`]: fmt/struct.Udp.html
+    pub fn udp(udp: fmt::Udp) -> Self {
+        let mut writer = writer::Builder::new();
+        writer.udp(udp);
+
+        Output {
+            writer,
+            level: LevelFilter::max(),
+            format: None,
+        }
+    }
+
+    /// Sets the most verbose level of records written to this output.
+    ///
+    /// Records still need to match the logger's filters to be written.
+    pub fn filter_level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Sets whether or not styles will be written to this output.
+    pub fn write_style(mut self, write_style: fmt::WriteStyle) -> Self {
+        self.writer.write_style(write_style);
+        self
+    }
+
+    /// Sets what to do when this output fails with a broken pipe.
+    ///
+    /// See [`BrokenPipePolicy`] for more details.
+    ///
+    /// [`BrokenPipePolicy`]: enum.BrokenPipePolicy.html
+    pub fn broken_pipe_policy(mut self, policy: fmt::BrokenPipePolicy) -> Self {
+        self.writer.broken_pipe(policy);
+        self
+    }
+
+    /// Sets what to do when a socket for this output can't be created.
+    ///
+    /// See [`ConnectErrorPolicy`] for more details.
+    ///
+    /// [`ConnectErrorPolicy`]: enum.ConnectErrorPolicy.html
+    pub fn connect_error_policy(mut self, policy: fmt::ConnectErrorPolicy) -> Self {
+        self.writer.connect_error(policy);
+        self
+    }
+
+    /// Sets the format function for records written to this output.
+    ///
+    /// By default an output uses the same format as the logger. Records are
+    /// formatted once for all outputs that share a format and style.
+    pub fn format<F>(mut self, format: F) -> Self
+    where
+        F: Fn(&mut Formatter, &Record) -> io::Result<()> + Sync + Send + 'static,
+    {
+        self.format = Some(Arc::new(format));
+        self
+    }
+}
+
+impl Logger {
+    /// Creates the logger from the environment.
+    ///
+    /// The variables used to read configuration from can be tweaked before
+    /// passing in.
+    ///
+    /// # Examples
+    ///
+    /// Create a logger reading the log filter from an environment variable
+    /// called `MY_LOG`:
+    ///
+    /// ```
+    /// use env_logger::Logger;
+    ///
+    /// let logger = Logger::from_env("MY_LOG");
+    /// ```
+    ///
+    /// Create a logger using the `MY_LOG` variable for filtering and
+    /// `MY_LOG_STYLE` for whether or not to write styles:
+    ///
+    /// ```
+    /// use env_logger::{Logger, Env};
+    ///
+    /// let env = Env::new().filter_or("MY_LOG", "info").write_style_or("MY_LOG_STYLE", "always");
+    ///
+    /// let logger = Logger::from_env(env);
+    /// ```
+    pub fn from_env<'a, E>(env: E) -> Self
+    where
+        E: Into<Env<'a>>,
+    {
+        Builder::from_env(env).build()
+    }
+
+    /// Creates the logger from the environment using default variable names.
+    ///
+    /// This method is a convenient way to call `from_env(Env::default())` without
+    /// having to use the `Env` type explicitly. The logger will use the
+    /// [default environment variables].
+    ///
+    /// # Examples
+    ///
+    /// Creates a logger using the default environment variables:
+    ///
+    /// ```
     /// use env_logger::Logger;
     ///
-    /// let logger = Logger::from_default_env();
+    /// let logger = Logger::from_default_env();
+    /// ```
+    ///
+    /// [default environment variables]: struct.Env.html#default-environment-variables
+    pub fn from_default_env() -> Self {
+        Builder::from_default_env().build()
+    }
+
+    /// Returns the maximum `LevelFilter` that this env logger instance is
+    /// configured to output.
+    pub fn filter(&self) -> LevelFilter {
+        self.filter.filter()
+    }
+
+    /// Checks if this record matches the configured filter.
+    pub fn matches(&self, record: &Record) -> bool {
+        self.filter.matches(record)
+    }
+
+    /// Returns the filter directives in effect, and where each one came from.
+    ///
+    /// See [`Filter::directives`] for more details.
+    ///
+    /// [`Filter::directives`]: filter/struct.Filter.html#method.directives
+    pub fn directives(&self) -> &[filter::Directive] {
+        self.filter.directives()
+    }
+
+    /// Returns a snapshot of the logger's statistics.
+    ///
+    /// See [`Stats`] for more details.
+    ///
+    /// [`Stats`]: struct.Stats.html
+    pub fn stats(&self) -> Stats {
+        self.counters.snapshot()
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let enabled = self.filter.enabled(metadata)
+            || (self.is_recorded(metadata.level()) && self.filter.recordable(metadata));
+
+        self.counters.enabled(metadata.level(), enabled);
+        enabled
+    }
+
+    fn log(&self, record: &Record) {
+        // A panic while the sinks are locked can't be logged by the panic hook
+        let _logging = panic::logging();
+        let level = record.level();
+
+        match self.filter.check(record) {
+            Verdict::Matched => {
+                // Records suppressed by the limits aren't printed, so they don't
+                // end a run of duplicates
+                if self.is_discarded(level)
+                    || !self.is_within_limits(record)
+                    || !self.is_new(record)
+                {
+                    self.counters.filtered(level);
+                    return;
+                }
+
+                if level == Level::Error {
+                    self.print_recorded();
+                }
+
+                self.print(record);
+                self.counters.emitted(level, record.target());
+            }
+            Verdict::Filtered { recordable } => {
+                self.counters.filtered(level);
+
+                if recordable && self.is_recorded(level) {
+                    self.record(record);
+                }
+            }
+            Verdict::Rejected => self.counters.rejected(),
+        }
+    }
+
+    fn flush(&self) {
+        let _logging = panic::logging();
+
+        if let Some(repeated) = self.dedup.as_ref().and_then(Deduplicator::flush) {
+            repeated.with_record(|record| self.print(record));
+        }
+
+        if let Some(ref limiter) = self.limiter {
+            let mut summaries = Vec::new();
+            limiter.flush(&mut summaries);
+            self.print_summaries(&summaries);
+        }
+
+        for sink in &self.sinks {
+            sink.writer.flush();
+        }
+    }
+}
+
+impl Logger {
+    /// Whether a record isn't a duplicate of the one printed before it.
+    ///
+    /// The repeat count of the record before it is printed if it's due.
+    fn is_new(&self, record: &Record) -> bool {
+        let dedup = match self.dedup {
+            Some(ref dedup) => dedup,
+            None => return true,
+        };
+
+        let mut repeated = None;
+        let is_new = dedup.check(record, &mut repeated);
+
+        if let Some(repeated) = repeated {
+            repeated.with_record(|record| self.print(record));
+        }
+
+        is_new
+    }
+
+    /// Whether a record is within the logger's rate limits.
+    ///
+    /// Summaries of records suppressed by the limits are printed as they're due.
+    fn is_within_limits(&self, record: &Record) -> bool {
+        let limiter = match self.limiter {
+            Some(ref limiter) => limiter,
+            None => return true,
+        };
+
+        let mut summaries = Vec::new();
+        let admitted = limiter.check(
+            record,
+            self.filter.rate_limit(record.metadata()),
+            &mut summaries,
+        );
+
+        self.print_summaries(&summaries);
+
+        admitted
+    }
+
+    /// Whether the logger has anything to print when time passes.
+    pub(crate) fn needs_ticks(&self) -> bool {
+        self.limiter.is_some() || self.dedup.is_some()
+    }
+
+    /// Print summaries and repeat counts that have become due.
+    pub(crate) fn tick(&self) {
+        let _logging = panic::logging();
+
+        if let Some(repeated) = self.dedup.as_ref().and_then(Deduplicator::tick) {
+            repeated.with_record(|record| self.print(record));
+        }
+
+        if let Some(ref limiter) = self.limiter {
+            let mut summaries = Vec::new();
+            limiter.tick(&mut summaries);
+            self.print_summaries(&summaries);
+        }
+    }
+
+    fn print_summaries(&self, summaries: &[Summary]) {
+        for summary in summaries {
+            self.print(
+                &Record::builder()
+                    .level(Level::Warn)
+                    .target("env_logger")
+                    .args(format_args!("{}", summary))
+                    .build(),
+            );
+        }
+    }
+
+    /// Print a record to each sink that accepts its level.
+    fn print(&self, record: &Record) {
+        let level = record.level();
+
+        for (i, sink) in self.sinks.iter().enumerate() {
+            if !sink.enabled(level) {
+                continue;
+            }
+
+            // Records are formatted once for all the sinks that share a format and
+            // buffer style. If an earlier sink shares them then it has already
+            // printed the record to this one.
+            if self.sinks[..i]
+                .iter()
+                .any(|other| other.enabled(level) && other.shares_buffer(sink, level))
+            {
+                continue;
+            }
+
+            // Errors are handled once the buffer is cleared, in case the policy panics
+            let errors = RefCell::new(Vec::new());
+
+            self.with_formatter(sink.writer.select(level), |formatter| {
+                match (self.formats[sink.format])(formatter, record) {
+                    Ok(()) => {
+                        // The logger's own target always gets the record first
+                        if i == 0 {
+                            capture::capture(record, || formatter.formatted());
+                        }
+
+                        for other in &self.sinks[i..] {
+                            if other.enabled(level) && other.shares_buffer(sink, level) {
+                                if let Err(e) = formatter.print(other.writer.select(level), record)
+                                {
+                                    errors.borrow_mut().push(e);
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => errors.borrow_mut().push(e),
+                }
+
+                // Always clear the buffer afterwards
+                formatter.clear();
+            });
+
+            for error in errors.into_inner() {
+                self.write_error(&error, record.metadata());
+            }
+        }
+
+        self.exit_if_broken();
+    }
+
+    /// Whether every target that would print a record at `level` is broken and
+    /// discarding records.
+    fn is_discarded(&self, level: Level) -> bool {
+        self.sinks
+            .iter()
+            .filter(|sink| sink.enabled(level))
+            .all(|sink| sink.writer.select(level).is_discarding())
+    }
+
+    /// Exit the process if a target is broken and its policy is to exit.
+    ///
+    /// The other targets, including their pipes, are flushed first, but nothing
+    /// else is cleaned up.
+    fn exit_if_broken(&self) {
+        if self.sinks.iter().any(|sink| sink.writer.is_exiting()) {
+            for sink in &self.sinks {
+                sink.writer.flush();
+            }
+
+            std::process::exit(0);
+        }
+    }
+
+    /// Count an error formatting or writing a record, and handle it with the policy.
+    fn write_error(&self, error: &io::Error, metadata: &Metadata) {
+        self.counters.write_error();
+        self.write_errors.handle(error, metadata);
+    }
+
+    /// The most verbose level of records this logger needs to see.
+    pub(crate) fn max_level(&self) -> LevelFilter {
+        match self.recorder {
+            Some(ref recorder) => std::cmp::max(self.filter(), recorder.level()),
+            None => self.filter(),
+        }
+    }
+
+    fn is_recorded(&self, level: Level) -> bool {
+        match self.recorder {
+            Some(ref recorder) => level <= recorder.level(),
+            None => false,
+        }
+    }
+
+    /// Format a filtered-out record into the flight recorder.
+    fn record(&self, record: &Record) {
+        let recorder = match self.recorder {
+            Some(ref recorder) => recorder,
+            None => return,
+        };
+
+        self.with_formatter(self.sinks[0].writer.select(record.level()), |formatter| {
+            if (self.formats[0])(formatter, record).is_ok() {
+                recorder.push(Recorded::new(record, formatter.buffer()));
+            }
+
+            formatter.clear();
+        });
+    }
+
+    fn handles_panics(&self) -> bool {
+        self.log_panics || self.recorder.is_some()
+    }
+
+    /// Print a panic on the current thread, along with any records kept by the flight recorder.
+    pub(crate) fn panicked(
+        &self,
+        location: Option<&std::panic::Location>,
+        payload: &(dyn std::any::Any + Send),
+    ) {
+        self.print_recorded();
+
+        if self.log_panics {
+            self.print(
+                &Record::builder()
+                    .level(Level::Error)
+                    .target("panic")
+                    .file(location.map(|location| location.file()))
+                    .line(location.map(|location| location.line()))
+                    .args(format_args!(
+                        "{}",
+                        panic::message(location, payload, self.panic_backtrace)
+                    ))
+                    .build(),
+            );
+
+            self.flush();
+        }
+    }
+
+    /// Print the records kept by the flight recorder to the logger's own target.
+    pub(crate) fn print_recorded(&self) {
+        let recorder = match self.recorder {
+            Some(ref recorder) => recorder,
+            None => return,
+        };
+
+        let sink = &self.sinks[0];
+        for recorded in recorder.drain() {
+            if let Err(e) = recorded.print(sink.writer.select(recorded.level())) {
+                self.write_error(&e, &recorded.metadata());
+            }
+        }
+
+        sink.writer.flush();
+        self.exit_if_broken();
+    }
+
+    fn with_formatter<F>(&self, writer: &Writer, print: F)
+    where
+        F: Fn(&mut Formatter),
+    {
+        // Log records are written to a thread-local buffer before being printed
+        // to the terminal. We clear these buffers afterwards, but they aren't shrinked
+        // so will always at least have capacity for the largest log record formatted
+        // on that thread.
+        //
+        // Writers with different color support need different buffers, so each thread
+        // keeps a formatter for each buffer style it has printed to.
+
+        thread_local! {
+            static FORMATTERS: RefCell<Vec<Formatter>> = RefCell::new(Vec::new());
+        }
+
+        let printed = FORMATTERS
+            .try_with(|tl_bufs| {
+                match tl_bufs.try_borrow_mut() {
+                    // There are no active borrows of the buffers
+                    Ok(mut tl_bufs) => {
+                        let buffer_style = writer.buffer_style();
+
+                        match tl_bufs
+                            .iter()
+                            .position(|formatter| formatter.buffer_style() == buffer_style)
+                        {
+                            // We have a previously set formatter for this style
+                            Some(i) => print(&mut tl_bufs[i]),
+                            // We don't have a previously set formatter for this style
+                            None => {
+                                let mut formatter = Formatter::new(writer);
+                                print(&mut formatter);
+
+                                tl_bufs.push(formatter);
+                            }
+                        }
+                    }
+                    // There's already an active borrow of the buffers (due to re-entrancy)
+                    Err(_) => {
+                        print(&mut Formatter::new(writer));
+                    }
+                }
+            })
+            .is_ok();
+
+        if !printed {
+            // The thread-local storage was not available (because its
+            // destructor has already run). Create a new single-use
+            // Formatter on the stack for this call.
+            print(&mut Formatter::new(writer));
+        }
+    }
+}
+
+impl Sink {
+    fn enabled(&self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    fn shares_buffer(&self, other: &Sink, level: Level) -> bool {
+        self.format == other.format
+            && self.writer.select(level).buffer_style() == other.writer.select(level).buffer_style()
+    }
+}
+
+impl<'a> Env<'a> {
+    /// Get a default set of environment variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a set of environment variables named after a prefix.
+    ///
+    /// Each variable's name is the prefix followed by the default name without
+    /// `RUST`, so the prefix `MY_APP` reads `MY_APP_LOG`, `MY_APP_LOG_STYLE`
+    /// and so on, including `MY_APP_LOG_CONFIG` for the path of a config file,
+    /// which the default set doesn't read.
+    ///
+    /// # Examples
+    ///
+    /// Read `MY_APP_LOG` and the other `MY_APP_LOG*` variables, falling back to
+    /// `RUST_LOG` and the other default variables when they aren't set:
+    ///
     /// ```
+    /// use env_logger::{Builder, Env};
     ///
-    /// [default environment variables]: struct.Env.html#default-environment-variables
-    pub fn from_default_env() -> Self {
-        Builder::from_default_env().build()
+    /// let env = Env::prefixed("MY_APP").fallback_to_rust_log(true);
+    ///
+    /// let mut builder = Builder::from_env(env);
+    /// ```
+    pub fn prefixed<P>(prefix: P) -> Self
+    where
+        P: AsRef<str>,
+    {
+        let prefix = prefix.as_ref();
+        let name = |default: &str| -> Cow<'a, str> {
+            format!("{}{}", prefix, default.trim_start_matches("RUST")).into()
+        };
+
+        Env {
+            filter: Var::new(name(DEFAULT_FILTER_ENV)),
+            write_style: Var::new(name(DEFAULT_WRITE_STYLE_ENV)),
+            config: Some(Var::new(name(DEFAULT_CONFIG_ENV))),
+            target: Var::new(name(DEFAULT_TARGET_ENV)),
+            timestamp: Var::new(name(DEFAULT_TIMESTAMP_ENV)),
+            format: Var::new(name(DEFAULT_FORMAT_ENV)),
+            indent: Var::new(name(DEFAULT_INDENT_ENV)),
+            fallback: false,
+        }
+    }
+
+    /// Whether to read the default `RUST_LOG*` variables when the configured
+    /// variables aren't set.
+    ///
+    /// A default variable is read before falling back to any default value.
+    /// This is disabled by default.
+    pub fn fallback_to_rust_log(mut self, fallback: bool) -> Self {
+        self.fallback = fallback;
+
+        self
+    }
+
+    fn fallback(&self, name: &'static str) -> Option<&'static str> {
+        if self.fallback {
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    /// Specify an environment variable to read the filter from.
+    pub fn filter<E>(mut self, filter_env: E) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+    {
+        self.filter = Var::new(filter_env);
+
+        self
+    }
+
+    /// Specify an environment variable to read the filter from.
+    ///
+    /// If the variable is not set, the default value will be used.
+    pub fn filter_or<E, V>(mut self, filter_env: E, default: V) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        self.filter = Var::new_with_default(filter_env, default);
+
+        self
+    }
+
+    /// Use the default environment variable to read the filter from.
+    ///
+    /// If the variable is not set, the default value will be used.
+    pub fn default_filter_or<V>(mut self, default: V) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        self.filter = Var::new_with_default(DEFAULT_FILTER_ENV, default);
+
+        self
+    }
+
+    fn get_filter(&self) -> Option<(String, filter::Source)> {
+        self.filter
+            .get_with_source(self.fallback(DEFAULT_FILTER_ENV))
+    }
+
+    /// Specify an environment variable to read the style from.
+    pub fn write_style<E>(mut self, write_style_env: E) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+    {
+        self.write_style = Var::new(write_style_env);
+
+        self
+    }
+
+    /// Specify an environment variable to read the style from.
+    ///
+    /// If the variable is not set, the default value will be used.
+    pub fn write_style_or<E, V>(mut self, write_style_env: E, default: V) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        self.write_style = Var::new_with_default(write_style_env, default);
+
+        self
+    }
+
+    /// Use the default environment variable to read the style from.
+    ///
+    /// If the variable is not set, the default value will be used.
+    pub fn default_write_style_or<V>(mut self, default: V) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        self.write_style = Var::new_with_default(DEFAULT_WRITE_STYLE_ENV, default);
+
+        self
+    }
+
+    fn get_write_style(&self) -> Option<String> {
+        self.write_style.get(self.fallback(DEFAULT_WRITE_STYLE_ENV))
+    }
+
+    /// Specify an environment variable to read the config file path from.
+    ///
+    /// No config file is loaded unless this, or one of the methods like it, is used.
+    pub fn config<E>(mut self, config_env: E) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+    {
+        self.config = Some(Var::new(config_env));
+
+        self
+    }
+
+    /// Specify an environment variable to read the config file path from.
+    ///
+    /// If the variable is not set, the default path will be used.
+    pub fn config_or<E, V>(mut self, config_env: E, default: V) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        self.config = Some(Var::new_with_default(config_env, default));
+
+        self
+    }
+
+    /// Use the default environment variable to read the config file path from.
+    ///
+    /// If the variable is not set, the default path will be used.
+    pub fn default_config_or<V>(mut self, default: V) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        self.config = Some(Var::new_with_default(DEFAULT_CONFIG_ENV, default));
+
+        self
+    }
+
+    fn get_config(&self) -> Option<String> {
+        self.config
+            .as_ref()
+            .and_then(|config| config.get(self.fallback(DEFAULT_CONFIG_ENV)))
+    }
+
+    /// Specify an environment variable to read the target from.
+    pub fn target<E>(mut self, target_env: E) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+    {
+        self.target = Var::new(target_env);
+
+        self
     }
 
-    /// Returns the maximum `LevelFilter` that this env logger instance is
-    /// configured to output.
-    pub fn filter(&self) -> LevelFilter {
-        self.filter.filter()
+    /// Specify an environment variable to read the target from.
+    ///
+    /// If the variable is not set, the default value will be used.
+    pub fn target_or<E, V>(mut self, target_env: E, default: V) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        self.target = Var::new_with_default(target_env, default);
+
+        self
     }
 
-    /// Checks if this record matches the configured filter.
-    pub fn matches(&self, record: &Record) -> bool {
-        self.filter.matches(record)
+    /// Use the default environment variable to read the target from.
+    ///
+    /// If the variable is not set, the default value will be used.
+    pub fn default_target_or<V>(mut self, default: V) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        self.target = Var::new_with_default(DEFAULT_TARGET_ENV, default);
+
+        self
     }
-}
 
-impl Log for Logger {
-    fn enabled(&self, metadata: &Metadata) -> bool {
-        self.filter.enabled(metadata)
+    fn get_target(&self) -> Option<(String, &str)> {
+        self.target.get_with_name(self.fallback(DEFAULT_TARGET_ENV))
     }
 
-    fn log(&self, record: &Record) {
-        if self.matches(record) {
-            // Log records are written to a thread-local buffer before being printed
-            // to the terminal. We clear these buffers afterwards, but they aren't shrinked
-            // so will always at least have capacity for the largest log record formatted
-            // on that thread.
-            //
-            // If multiple `Logger`s are used by the same threads then the thread-local
-            // formatter might have different color support. If this is the case the
-            // formatter and its buffer are discarded and recreated.
-
-            thread_local! {
-                static FORMATTER: RefCell<Option<Formatter>> = RefCell::new(None);
-            }
+    /// Specify an environment variable to read the timestamp precision from.
+    pub fn timestamp<E>(mut self, timestamp_env: E) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+    {
+        self.timestamp = Var::new(timestamp_env);
 
-            let print = |formatter: &mut Formatter, record: &Record| {
-                let _ =
-                    (self.format)(formatter, record).and_then(|_| formatter.print(&self.writer));
+        self
+    }
 
-                // Always clear the buffer afterwards
-                formatter.clear();
-            };
+    /// Specify an environment variable to read the timestamp precision from.
+    ///
+    /// If the variable is not set, the default value will be used.
+    pub fn timestamp_or<E, V>(mut self, timestamp_env: E, default: V) -> Self
+    where
+        E: Into<Cow<'a, str>>,
+        V: Into<Cow<'a, str>>,
+    {
+        self.timestamp = Var::new_with_default(timestamp_env, default);
 
-            let printed = FORMATTER
-                .try_with(|tl_buf| {
-                    match tl_buf.try_borrow_mut() {
-                        // There are no active borrows of the buffer
-                        Ok(mut tl_buf) => match *tl_buf {
-                            // We have a previously set formatter
-                            Some(ref mut formatter) => {
-                                // Check the buffer style. If it's different from the logger's
-                                // style then drop the buffer and recreate it.
-                                if formatter.write_style() != self.writer.write_style() {
-                                    *formatter = Formatter::new(&self.writer);
-                                }
+        self
+    }
 
-                                print(formatter, record);
-                            }
-                            // We don't have a previously set formatter
-                            None => {
-                                let mut formatter = Formatter::new(&self.writer);
-                                print(&mut formatter, record);
+    /// Use the default environment variable to read the timestamp precision from.
+    ///
+    /// If the variable is not set, the default value will be used.
+    pub fn default_timestamp_or<V>(mut self, default: V) -> Self
+    where
+        V: Into<Cow<'a, str>>,
+    {
+        self.timestamp = Var::new_with_default(DEFAULT_TIMESTAMP_ENV, default);
 
-                                *tl_buf = Some(formatter);
-                            }
-                        },
-                        // There's already an active borrow of the buffer (due to re-entrancy)
-                        Err(_) => {
-                            print(&mut Formatter::new(&self.writer), record);
-                        }
-                    }
-                })
-                .is_ok();
-
-            if !printed {
-                // The thread-local storage was not available (because its
-                // destructor has already run). Create a new single-use
-                // Formatter on the stack for this call.
-                print(&mut Formatter::new(&self.writer), record);
-            }
-        }
+        self
     }
 
-    fn flush(&self) {}
-}
-
-impl<'a> Env<'a> {
-    /// Get a default set of environment variables.
-    pub fn new() -> Self {
-        Self::default()
+    fn get_timestamp(&self) -> Option<(String, &str)> {
+        self.timestamp
+            .get_with_name(self.fallback(DEFAULT_TIMESTAMP_ENV))
     }
 
-    /// Specify an environment variable to read the filter from.
-    pub fn filter<E>(mut self, filter_env: E) -> Self
+    /// Specify an environment variable to read default format options from.
+    pub fn format<E>(mut self, format_env: E) -> Self
     where
         E: Into<Cow<'a, str>>,
     {
-        self.filter = Var::new(filter_env);
+        self.format = Var::new(format_env);
 
         self
     }
 
-    /// Specify an environment variable to read the filter from.
+    /// Specify an environment variable to read default format options from.
     ///
     /// If the variable is not set, the default value will be used.
-    pub fn filter_or<E, V>(mut self, filter_env: E, default: V) -> Self
+    pub fn format_or<E, V>(mut self, format_env: E, default: V) -> Self
     where
         E: Into<Cow<'a, str>>,
         V: Into<Cow<'a, str>>,
     {
-        self.filter = Var::new_with_default(filter_env, default);
+        self.format = Var::new_with_default(format_env, default);
 
         self
     }
 
-    /// Use the default environment variable to read the filter from.
+    /// Use the default environment variable to read default format options from.
     ///
     /// If the variable is not set, the default value will be used.
-    pub fn default_filter_or<V>(mut self, default: V) -> Self
+    pub fn default_format_or<V>(mut self, default: V) -> Self
     where
         V: Into<Cow<'a, str>>,
     {
-        self.filter = Var::new_with_default(DEFAULT_FILTER_ENV, default);
+        self.format = Var::new_with_default(DEFAULT_FORMAT_ENV, default);
 
         self
     }
 
-    fn get_filter(&self) -> Option<String> {
-        self.filter.get()
+    fn get_format(&self) -> Option<(String, &str)> {
+        self.format.get_with_name(self.fallback(DEFAULT_FORMAT_ENV))
     }
 
-    /// Specify an environment variable to read the style from.
-    pub fn write_style<E>(mut self, write_style_env: E) -> Self
+    /// Specify an environment variable to read the indentation from.
+    pub fn indent<E>(mut self, indent_env: E) -> Self
     where
         E: Into<Cow<'a, str>>,
     {
-        self.write_style = Var::new(write_style_env);
+        self.indent = Var::new(indent_env);
 
         self
     }
 
-    /// Specify an environment variable to read the style from.
+    /// Specify an environment variable to read the indentation from.
     ///
     /// If the variable is not set, the default value will be used.
-    pub fn write_style_or<E, V>(mut self, write_style_env: E, default: V) -> Self
+    pub fn indent_or<E, V>(mut self, indent_env: E, default: V) -> Self
     where
         E: Into<Cow<'a, str>>,
         V: Into<Cow<'a, str>>,
     {
-        self.write_style = Var::new_with_default(write_style_env, default);
+        self.indent = Var::new_with_default(indent_env, default);
 
         self
     }
 
-    /// Use the default environment variable to read the style from.
+    /// Use the default environment variable to read the indentation from.
     ///
     /// If the variable is not set, the default value will be used.
-    pub fn default_write_style_or<V>(mut self, default: V) -> Self
+    pub fn default_indent_or<V>(mut self, default: V) -> Self
     where
         V: Into<Cow<'a, str>>,
     {
-        self.write_style = Var::new_with_default(DEFAULT_WRITE_STYLE_ENV, default);
+        self.indent = Var::new_with_default(DEFAULT_INDENT_ENV, default);
 
         self
     }
 
-    fn get_write_style(&self) -> Option<String> {
-        self.write_style.get()
+    fn get_indent(&self) -> Option<(String, &str)> {
+        self.indent.get_with_name(self.fallback(DEFAULT_INDENT_ENV))
     }
 }
 
@@ -965,13 +2591,51 @@ impl<'a> Var<'a> {
         }
     }
 
-    fn get(&self) -> Option<String> {
+    fn get(&self, fallback: Option<&str>) -> Option<String> {
+        self.get_with_source(fallback).map(|(value, _)| value)
+    }
+
+    /// Get the value along with the name of the variable it was read from.
+    ///
+    /// A default value is named after the configured variable.
+    fn get_with_name<'s>(&'s self, fallback: Option<&'s str>) -> Option<(String, &'s str)> {
+        if let Ok(value) = env::var(&*self.name) {
+            return Some((value, &self.name));
+        }
+
+        if let Some(fallback) = fallback {
+            if let Ok(value) = env::var(fallback) {
+                return Some((value, fallback));
+            }
+        }
+
+        self.default
+            .as_ref()
+            .map(|default| (default.to_string(), &*self.name))
+    }
+
+    /// Get the value along with whether it came from the environment or the default.
+    fn get_with_source(&self, fallback: Option<&str>) -> Option<(String, filter::Source)> {
         env::var(&*self.name)
             .ok()
-            .or_else(|| self.default.to_owned().map(|v| v.into_owned()))
+            .or_else(|| fallback.and_then(|fallback| env::var(fallback).ok()))
+            .map(|value| (value, filter::Source::Environment))
+            .or_else(|| {
+                self.default
+                    .to_owned()
+                    .map(|v| (v.into_owned(), filter::Source::Default))
+            })
     }
 }
 
+/// Warn about an invalid value read from a variable that's being ignored.
+fn invalid(var: &str, value: &str, expected: &str) {
+    eprintln!(
+        "warning: invalid value '{}' for {}, expected {}, ignoring it",
+        value, var, expected
+    );
+}
+
 impl<'a, T> From<T> for Env<'a>
 where
     T: Into<Cow<'a, str>>,
@@ -986,6 +2650,12 @@ impl<'a> Default for Env<'a> {
         Env {
             filter: Var::new(DEFAULT_FILTER_ENV),
             write_style: Var::new(DEFAULT_WRITE_STYLE_ENV),
+            config: None,
+            target: Var::new(DEFAULT_TARGET_ENV),
+            timestamp: Var::new(DEFAULT_TIMESTAMP_ENV),
+            format: Var::new(DEFAULT_FORMAT_ENV),
+            indent: Var::new(DEFAULT_INDENT_ENV),
+            fallback: false,
         }
     }
 }
@@ -1004,14 +2674,28 @@ mod std_fmt_impls {
 
     impl fmt::Debug for Builder {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            if self.built {
-                f.debug_struct("Logger").field("built", &true).finish()
-            } else {
-                f.debug_struct("Logger")
-                    .field("filter", &self.filter)
-                    .field("writer", &self.writer)
-                    .finish()
-            }
+            f.debug_struct("Logger")
+                .field("filter", &self.filter)
+                .field("writer", &self.writer)
+                .field("outputs", &self.outputs)
+                .field("recorder", &self.recorder)
+                .field("rate_limit", &self.rate_limit)
+                .field("rate_limit_per_callsite", &self.rate_limit_per_callsite)
+                .field("collapse_duplicates", &self.collapse_duplicates)
+                .field("count_targets", &self.count_targets)
+                .field("write_error_policy", &self.write_error_policy)
+                .field("log_panics", &self.log_panics)
+                .field("panic_backtrace", &self.panic_backtrace)
+                .finish()
+        }
+    }
+
+    impl fmt::Debug for Output {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("Output")
+                .field("writer", &self.writer)
+                .field("level", &self.level)
+                .finish()
         }
     }
 }
@@ -1128,17 +2812,77 @@ where
     Builder::from_env(env)
 }
 
+/// Returns a snapshot of the installed logger's statistics.
+///
+/// The statistics come from the logger that records on the current thread are
+/// sent to. That's a scoped logger if one is installed, and otherwise the global
+/// logger. Returns `None` if no logger has been installed by env_logger.
+///
+/// See [`Stats`] for more details.
+///
+/// [`Stats`]: struct.Stats.html
+pub fn stats() -> Option<Stats> {
+    scope::with_logger(Composite::stats)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedBuf {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn log(logger: &Logger, level: Level, args: std::fmt::Arguments) {
+        logger.log(
+            &Record::builder()
+                .args(args)
+                .level(level)
+                .target("test::path")
+                .build(),
+        );
+    }
+
     #[test]
     fn env_get_filter_reads_from_var_if_set() {
         env::set_var("env_get_filter_reads_from_var_if_set", "from var");
 
         let env = Env::new().filter_or("env_get_filter_reads_from_var_if_set", "from default");
 
-        assert_eq!(Some("from var".to_owned()), env.get_filter());
+        assert_eq!(
+            Some(("from var".to_owned(), filter::Source::Environment)),
+            env.get_filter()
+        );
     }
 
     #[test]
@@ -1150,7 +2894,10 @@ mod tests {
             "from default",
         );
 
-        assert_eq!(Some("from default".to_owned()), env.get_filter());
+        assert_eq!(
+            Some(("from default".to_owned(), filter::Source::Default)),
+            env.get_filter()
+        );
     }
 
     #[test]
@@ -1163,6 +2910,138 @@ mod tests {
         assert_eq!(Some("from var".to_owned()), env.get_write_style());
     }
 
+    #[test]
+    fn env_get_config_is_opt_in() {
+        env::set_var("env_get_config_is_opt_in", "log.toml");
+
+        assert_eq!(None, Env::new().get_config());
+        assert_eq!(
+            Some("log.toml".to_owned()),
+            Env::new().config("env_get_config_is_opt_in").get_config()
+        );
+    }
+
+    #[test]
+    fn env_default_names_every_var() {
+        let env = Env::default();
+
+        assert_eq!(DEFAULT_FILTER_ENV, env.filter.name);
+        assert_eq!(DEFAULT_WRITE_STYLE_ENV, env.write_style.name);
+        assert!(env.config.is_none());
+        assert_eq!(DEFAULT_TARGET_ENV, env.target.name);
+        assert_eq!(DEFAULT_TIMESTAMP_ENV, env.timestamp.name);
+        assert_eq!(DEFAULT_FORMAT_ENV, env.format.name);
+        assert_eq!(DEFAULT_INDENT_ENV, env.indent.name);
+    }
+
+    #[test]
+    fn env_get_target_reads_from_var_if_set() {
+        env::set_var("env_get_target_reads_from_var_if_set", "stdout");
+
+        let env = Env::new().target("env_get_target_reads_from_var_if_set");
+
+        assert_eq!(
+            Some(("stdout".to_owned(), "env_get_target_reads_from_var_if_set")),
+            env.get_target()
+        );
+    }
+
+    #[test]
+    fn env_get_names_the_var_that_was_read() {
+        env::remove_var("env_get_names_the_var_that_was_read");
+        env::set_var("env_get_names_the_var_that_was_read_fallback", "hours");
+
+        let var = Var::new_with_default("env_get_names_the_var_that_was_read", "off");
+
+        assert_eq!(
+            Some((
+                "hours".to_owned(),
+                "env_get_names_the_var_that_was_read_fallback"
+            )),
+            var.get_with_name(Some("env_get_names_the_var_that_was_read_fallback"))
+        );
+        assert_eq!(
+            Some(("off".to_owned(), "env_get_names_the_var_that_was_read")),
+            var.get_with_name(None)
+        );
+    }
+
+    #[test]
+    fn env_prefixed_names_every_var() {
+        let env = Env::prefixed("ENV_PREFIXED_NAMES");
+
+        assert_eq!("ENV_PREFIXED_NAMES_LOG", env.filter.name);
+        assert_eq!("ENV_PREFIXED_NAMES_LOG_STYLE", env.write_style.name);
+        assert_eq!("ENV_PREFIXED_NAMES_LOG_CONFIG", env.config.unwrap().name);
+        assert_eq!("ENV_PREFIXED_NAMES_LOG_TARGET", env.target.name);
+        assert_eq!("ENV_PREFIXED_NAMES_LOG_TIMESTAMP", env.timestamp.name);
+        assert_eq!("ENV_PREFIXED_NAMES_LOG_FORMAT", env.format.name);
+        assert_eq!("ENV_PREFIXED_NAMES_LOG_INDENT", env.indent.name);
+    }
+
+    #[test]
+    fn env_prefixed_falls_back_to_default_vars() {
+        env::set_var("ENV_PREFIXED_FALLBACK_LOG", "from prefixed");
+        env::remove_var("ENV_PREFIXED_FALLBACK_LOG_STYLE");
+        env::set_var(
+            "env_prefixed_falls_back_to_default_vars",
+            "from default var",
+        );
+
+        let env = Env::prefixed("ENV_PREFIXED_FALLBACK");
+        assert_eq!(None, env.fallback(DEFAULT_WRITE_STYLE_ENV));
+
+        let env = env.fallback_to_rust_log(true);
+        assert_eq!(
+            Some(DEFAULT_WRITE_STYLE_ENV),
+            env.fallback(DEFAULT_WRITE_STYLE_ENV)
+        );
+
+        // Stand in for the default variables, so the tests' environment isn't changed
+        let fallback = Some("env_prefixed_falls_back_to_default_vars");
+        assert_eq!(Some("from prefixed".to_owned()), env.filter.get(fallback));
+        assert_eq!(
+            Some("from default var".to_owned()),
+            env.write_style.get(fallback)
+        );
+    }
+
+    #[test]
+    fn from_env_applies_format_settings() {
+        env::set_var("from_env_applies_format_settings_indent", "2");
+
+        // Every variable has a unique name, so the tests' environment isn't read
+        let builder = Builder::from_env(
+            Env::prefixed("FROM_ENV_APPLIES_FORMAT_SETTINGS")
+                .timestamp_or("from_env_applies_format_settings_timestamp", "off")
+                .format_or(
+                    "from_env_applies_format_settings_format",
+                    "-level, escape,-module_path",
+                )
+                .indent("from_env_applies_format_settings_indent"),
+        );
+
+        assert!(builder.format.format_timestamp.is_none());
+        assert!(!builder.format.format_level);
+        assert!(!builder.format.format_module_path);
+        assert!(builder.format.format_escape);
+        assert_eq!(Some(2), builder.format.format_indent);
+    }
+
+    #[test]
+    fn from_env_ignores_invalid_settings() {
+        let builder = Builder::from_env(
+            Env::prefixed("FROM_ENV_IGNORES_INVALID_SETTINGS")
+                .timestamp_or("from_env_ignores_invalid_settings_timestamp", "hours")
+                .format_or("from_env_ignores_invalid_settings_format", "colour,-level")
+                .indent_or("from_env_ignores_invalid_settings_indent", "lots"),
+        );
+
+        assert!(builder.format.format_timestamp.is_some());
+        assert!(!builder.format.format_level);
+        assert_eq!(Some(4), builder.format.format_indent);
+    }
+
     #[test]
     fn env_get_write_style_reads_from_default_if_var_not_set() {
         env::remove_var("env_get_write_style_reads_from_default_if_var_not_set");
@@ -1174,4 +3053,420 @@ mod tests {
 
         assert_eq!(Some("from default".to_owned()), env.get_write_style());
     }
+
+    #[test]
+    fn outputs_filter_by_level() {
+        let all = SharedBuf::default();
+        let warnings = SharedBuf::default();
+
+        let logger = Builder::new()
+            .filter_level(LevelFilter::Debug)
+            .format(|buf, record| writeln!(buf, "{} {}", record.level(), record.args()))
+            .pipe(Box::new(all.clone()))
+            .add_output(Output::pipe(Box::new(warnings.clone())).filter_level(LevelFilter::Warn))
+            .build();
+
+        log(&logger, Level::Trace, format_args!("trace"));
+        log(&logger, Level::Debug, format_args!("debug"));
+        log(&logger, Level::Error, format_args!("error"));
+
+        assert_eq!("DEBUG debug\nERROR error\n", all.contents());
+        assert_eq!("ERROR error\n", warnings.contents());
+    }
+
+    #[test]
+    fn outputs_use_their_own_format() {
+        let default = SharedBuf::default();
+        let custom = SharedBuf::default();
+
+        let logger = Builder::new()
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| writeln!(buf, "{}", record.args()))
+            .pipe(Box::new(default.clone()))
+            .add_output(
+                Output::pipe(Box::new(custom.clone()))
+                    .format(|buf, record| writeln!(buf, "{}: {}", record.level(), record.args())),
+            )
+            .build();
+
+        log(&logger, Level::Info, format_args!("message"));
+
+        assert_eq!("message\n", default.contents());
+        assert_eq!("INFO: message\n", custom.contents());
+    }
+
+    #[test]
+    fn stats_count_records() {
+        let logger = Builder::new()
+            .parse_filters("info/keep")
+            .count_targets(1)
+            .pipe(Box::new(Broken))
+            .build();
+
+        log(&logger, Level::Debug, format_args!("keep"));
+        log(&logger, Level::Info, format_args!("keep"));
+        log(&logger, Level::Info, format_args!("drop"));
+        log(&logger, Level::Error, format_args!("keep"));
+
+        let metadata = |level| Metadata::builder().level(level).build();
+        assert!(logger.enabled(&metadata(Level::Info)));
+        assert!(!logger.enabled(&metadata(Level::Debug)));
+
+        let stats = logger.stats();
+
+        assert_eq!(1, stats.enabled(Level::Info));
+        assert_eq!(1, stats.disabled(Level::Debug));
+
+        assert_eq!(1, stats.emitted(Level::Info));
+        assert_eq!(1, stats.emitted(Level::Error));
+        assert_eq!(1, stats.filtered(Level::Debug));
+        assert_eq!(1, stats.rejected());
+        assert_eq!(2, stats.write_errors());
+        assert_eq!(&[("test::path".to_owned(), 2)], stats.targets());
+    }
+
+    #[test]
+    fn write_error_policy_calls_back_with_metadata() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+
+        let logger = {
+            let errors = errors.clone();
+
+            Builder::new()
+                .filter_level(LevelFilter::Info)
+                .pipe(Box::new(Broken))
+                .write_error_policy(WriteErrorPolicy::callback(move |error, metadata| {
+                    errors.lock().unwrap().push((
+                        error.kind(),
+                        metadata.level(),
+                        metadata.target().to_owned(),
+                    ));
+                }))
+                .build()
+        };
+
+        log(&logger, Level::Warn, format_args!("message"));
+
+        assert_eq!(
+            vec![(
+                io::ErrorKind::BrokenPipe,
+                Level::Warn,
+                "test::path".to_owned()
+            )],
+            *errors.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn write_error_policy_callback_can_log() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let shared: Arc<Mutex<Option<Arc<Logger>>>> = Arc::default();
+
+        let policy = {
+            let calls = calls.clone();
+            let shared = shared.clone();
+
+            WriteErrorPolicy::callback(move |_, _| {
+                calls.fetch_add(1, Ordering::Relaxed);
+
+                // Logging to the same broken target doesn't call back again
+                let logger = shared.lock().unwrap().clone().unwrap();
+                log(&logger, Level::Error, format_args!("failed to write"));
+            })
+        };
+
+        let logger = Arc::new(
+            Builder::new()
+                .filter_level(LevelFilter::Info)
+                .pipe(Box::new(Broken))
+                .write_error_policy(policy)
+                .build(),
+        );
+        *shared.lock().unwrap() = Some(logger.clone());
+
+        log(&logger, Level::Warn, format_args!("first"));
+        log(&logger, Level::Warn, format_args!("second"));
+
+        assert_eq!(2, calls.load(Ordering::Relaxed));
+
+        // The callback holds on to the logger, so break the cycle
+        shared.lock().unwrap().take();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "failed to write a log record for `test::path`")]
+    fn write_error_policy_panics_in_debug() {
+        let logger = Builder::new()
+            .filter_level(LevelFilter::Info)
+            .pipe(Box::new(Broken))
+            .write_error_policy(WriteErrorPolicy::PanicInDebug)
+            .build();
+
+        log(&logger, Level::Info, format_args!("message"));
+    }
+
+    #[test]
+    fn builder_can_build_more_than_once() {
+        let buf = SharedBuf::default();
+
+        let mut builder = Builder::new();
+        builder
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| writeln!(buf, "{}", record.args()))
+            .pipe(Box::new(buf.clone()));
+
+        let first = builder.build();
+        let second = builder.build();
+
+        log(&first, Level::Info, format_args!("first"));
+        log(&second, Level::Info, format_args!("second"));
+
+        assert_eq!("first\nsecond\n", buf.contents());
+    }
+
+    #[test]
+    fn cloned_builders_are_independent() {
+        let info = SharedBuf::default();
+        let debug = SharedBuf::default();
+
+        let mut template = Builder::new();
+        template
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| writeln!(buf, "{}", record.args()));
+
+        let info_logger = template.clone().pipe(Box::new(info.clone())).build();
+        let debug_logger = template
+            .clone()
+            .filter_level(LevelFilter::Debug)
+            .pipe(Box::new(debug.clone()))
+            .build();
+
+        log(&info_logger, Level::Debug, format_args!("debug"));
+        log(&debug_logger, Level::Debug, format_args!("debug"));
+
+        assert_eq!("", info.contents());
+        assert_eq!("debug\n", debug.contents());
+        assert_eq!(LevelFilter::Info, template.build().filter());
+    }
+
+    #[test]
+    fn outputs_format_once_per_distinct_format() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static FORMATTED: AtomicUsize = AtomicUsize::new(0);
+
+        let first = SharedBuf::default();
+        let second = SharedBuf::default();
+
+        let logger = Builder::new()
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| {
+                FORMATTED.fetch_add(1, Ordering::SeqCst);
+                writeln!(buf, "{}", record.args())
+            })
+            .pipe(Box::new(first.clone()))
+            .add_output(Output::pipe(Box::new(second.clone())))
+            .build();
+
+        log(&logger, Level::Info, format_args!("message"));
+
+        assert_eq!(1, FORMATTED.load(Ordering::SeqCst));
+        assert_eq!("message\n", first.contents());
+        assert_eq!("message\n", second.contents());
+    }
+
+    #[test]
+    fn flight_recorder_prints_before_errors() {
+        let buf = SharedBuf::default();
+
+        let logger = Builder::new()
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| writeln!(buf, "{} {}", record.level(), record.args()))
+            .pipe(Box::new(buf.clone()))
+            .flight_recorder(FlightRecorder::new(LevelFilter::Debug).capacity(2))
+            .build();
+
+        log(&logger, Level::Debug, format_args!("a"));
+        log(&logger, Level::Trace, format_args!("not recorded"));
+        log(&logger, Level::Debug, format_args!("b"));
+        log(&logger, Level::Info, format_args!("info"));
+        log(&logger, Level::Debug, format_args!("c"));
+
+        assert_eq!("INFO info\n", buf.contents());
+
+        log(&logger, Level::Error, format_args!("error"));
+        log(&logger, Level::Error, format_args!("another error"));
+
+        assert_eq!(
+            "INFO info\nDEBUG b\nDEBUG c\nERROR error\nERROR another error\n",
+            buf.contents()
+        );
+    }
+
+    #[test]
+    fn flight_recorder_respects_deliberate_filters() {
+        let buf = SharedBuf::default();
+
+        let logger = Builder::new()
+            .parse_filters("info,hyper=off,noisy=warn/r")
+            .format(|buf, record| writeln!(buf, "{} {}", record.target(), record.args()))
+            .pipe(Box::new(buf.clone()))
+            .flight_recorder(FlightRecorder::new(LevelFilter::Trace))
+            .build();
+
+        let log = |target, level, message| {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(level)
+                    .target(target)
+                    .build(),
+            );
+        };
+
+        log("hyper", Level::Error, "off");
+        log("hyper", Level::Debug, "off");
+        log("noisy", Level::Info, "stricter");
+        log("noisy", Level::Warn, "skip");
+        log("app", Level::Debug, "recorded");
+
+        assert!(!logger.enabled(&Metadata::builder().target("hyper").build()));
+        assert!(!logger.enabled(
+            &Metadata::builder()
+                .level(Level::Info)
+                .target("noisy")
+                .build()
+        ));
+
+        log("app", Level::Error, "error");
+
+        assert_eq!("app recorded\napp error\n", buf.contents());
+    }
+
+    #[test]
+    fn flight_recorder_raises_max_level() {
+        let logger = Builder::new()
+            .filter_level(LevelFilter::Warn)
+            .flight_recorder(FlightRecorder::new(LevelFilter::Debug))
+            .build();
+
+        assert_eq!(LevelFilter::Warn, logger.filter());
+        assert_eq!(LevelFilter::Debug, logger.max_level());
+
+        assert!(logger.enabled(&Metadata::builder().level(Level::Debug).build()));
+        assert!(!logger.enabled(&Metadata::builder().level(Level::Trace).build()));
+    }
+
+    #[test]
+    fn rate_limit_suppresses_records() {
+        let buf = SharedBuf::default();
+
+        let logger = Builder::new()
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| writeln!(buf, "{} {}", record.level(), record.args()))
+            .pipe(Box::new(buf.clone()))
+            .rate_limit_per_callsite(RateLimit::new(2, Duration::from_secs(60)))
+            .build();
+
+        for i in 0..5 {
+            log(&logger, Level::Info, format_args!("{}", i));
+        }
+
+        assert_eq!("INFO 0\nINFO 1\n", buf.contents());
+
+        logger.flush();
+
+        assert_eq!(
+            "INFO 0\nINFO 1\nWARN suppressed 3 records from test::path in the last 60s\n",
+            buf.contents()
+        );
+    }
+
+    #[test]
+    fn collapse_duplicates_prints_repeat_count() {
+        let buf = SharedBuf::default();
+
+        let logger = Builder::new()
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| writeln!(buf, "{} {}", record.level(), record.args()))
+            .pipe(Box::new(buf.clone()))
+            .collapse_duplicates(Duration::from_secs(60))
+            .build();
+
+        for _ in 0..3 {
+            log(&logger, Level::Info, format_args!("again"));
+        }
+        log(&logger, Level::Warn, format_args!("different"));
+        log(&logger, Level::Warn, format_args!("different"));
+
+        assert_eq!(
+            "INFO again\nINFO last message repeated 2 times\nWARN different\n",
+            buf.contents()
+        );
+
+        logger.flush();
+
+        assert_eq!(
+            "INFO again\nINFO last message repeated 2 times\nWARN different\n\
+             WARN last message repeated 1 times\n",
+            buf.contents()
+        );
+    }
+
+    #[test]
+    fn rate_limited_records_do_not_end_duplicates() {
+        let buf = SharedBuf::default();
+
+        let logger = Builder::new()
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| writeln!(buf, "{} {}", record.level(), record.args()))
+            .pipe(Box::new(buf.clone()))
+            .rate_limit_per_callsite(RateLimit::new(1, Duration::from_secs(60)))
+            .collapse_duplicates(Duration::from_secs(60))
+            .build();
+
+        let log = |line, message| {
+            logger.log(
+                &Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(Level::Info)
+                    .target("test::path")
+                    .file(Some("src/x.rs"))
+                    .line(Some(line))
+                    .build(),
+            );
+        };
+
+        log(1, "again");
+        log(2, "again");
+        log(1, "suppressed");
+        log(3, "again");
+
+        logger.flush();
+
+        assert_eq!(
+            "INFO again\nINFO last message repeated 2 times\n\
+             WARN suppressed 1 records from src/x.rs:1 in the last 60s\n",
+            buf.contents()
+        );
+    }
+
+    #[test]
+    fn rate_limit_from_directives() {
+        let buf = SharedBuf::default();
+
+        let logger = Builder::new()
+            .parse_filters("info,test::path=warn~1")
+            .format(|buf, record| writeln!(buf, "{} {}", record.level(), record.args()))
+            .pipe(Box::new(buf.clone()))
+            .build();
+
+        log(&logger, Level::Warn, format_args!("a"));
+        log(&logger, Level::Warn, format_args!("b"));
+
+        assert_eq!("WARN a\n", buf.contents());
+    }
 }
@@ -0,0 +1,481 @@
+/*
+This internal module contains the rate limiter.
+
+Records are counted in fixed windows, either for the whole logger or for each
+callsite. Once a window's limit is reached, later records in that window are
+suppressed. When the window ends, a summary of how many records were suppressed
+is logged in their place, either by the next record or by the ticker.
+*/
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use log::Record;
+
+// How often windows that have ended are looked for, so their summaries are
+// logged even if their callsite stops logging
+const SWEEP_INTERVAL_SECS: u64 = 1;
+
+/// A limit on how many records can be logged in a period of time.
+///
+/// Limits can be applied to all the records logged with [`Builder::rate_limit`],
+/// or to each callsite with [`Builder::rate_limit_per_callsite`]. A callsite is a
+/// single place in the code where a record is logged from, identified by its
+/// module path, file and line.
+///
+/// Limits can also be set for each callsite in a module by adding `~N` to a filter
+/// directive, where `N` is the number of records allowed each second. A different
+/// period can be given after a `:`, like `~100:10s`. Periods can be in `ms`, `s`,
+/// `m` or `h`.
+///
+/// Records beyond the limit are suppressed until the period ends. Then a warning
+/// with the `env_logger` target is logged, saying how many records were suppressed.
+/// An installed logger logs it within about a second of the period ending. A logger
+/// that's only built logs it with the next record, or when it's flushed.
+///
+/// # Examples
+///
+/// Log at most 10 records from each callsite every 10 seconds:
+///
+/// ```
+/// use std::time::Duration;
+/// use env_logger::{Builder, RateLimit};
+///
+/// let mut builder = Builder::new();
+///
+/// builder.rate_limit_per_callsite(RateLimit::new(10, Duration::from_secs(10)));
+/// ```
+///
+/// Log at most 5 records a second from each callsite in the `hot_loop` module:
+///
+/// ```
+/// use env_logger::Builder;
+///
+/// let mut builder = Builder::new();
+///
+/// builder.parse_filters("info,hot_loop=warn~5");
+/// ```
+///
+/// [`Builder::rate_limit`]: struct.Builder.html#method.rate_limit
+/// [`Builder::rate_limit_per_callsite`]: struct.Builder.html#method.rate_limit_per_callsite
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct RateLimit {
+    records: u32,
+    period: Duration,
+}
+
+impl RateLimit {
+    /// Allow up to `records` records in each `period`.
+    pub fn new(records: u32, period: Duration) -> Self {
+        RateLimit { records, period }
+    }
+
+    /// The number of records allowed in each period.
+    pub fn records(&self) -> u32 {
+        self.records
+    }
+
+    /// The length of each period.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+/// Records suppressed by a limit during a window that has ended.
+pub(crate) struct Summary {
+    // The callsite the records came from, if they were limited per callsite
+    location: Option<String>,
+    suppressed: u64,
+    period: Duration,
+}
+
+pub(crate) struct Limiter {
+    global: Option<(RateLimit, Mutex<Window>)>,
+    per_callsite: Option<RateLimit>,
+    callsites: Mutex<Callsites>,
+}
+
+struct Callsites {
+    windows: HashMap<u64, Window>,
+    next_sweep: Instant,
+}
+
+struct Window {
+    limit: RateLimit,
+    location: Option<String>,
+    start: Instant,
+    count: u32,
+    suppressed: u64,
+}
+
+impl Limiter {
+    pub(crate) fn new(global: Option<RateLimit>, per_callsite: Option<RateLimit>) -> Self {
+        let now = Instant::now();
+
+        Limiter {
+            global: global.map(|limit| (limit, Mutex::new(Window::new(limit, None, now)))),
+            per_callsite,
+            callsites: Mutex::new(Callsites {
+                windows: HashMap::new(),
+                next_sweep: now + Duration::from_secs(SWEEP_INTERVAL_SECS),
+            }),
+        }
+    }
+
+    /// Whether a record is within its limits.
+    ///
+    /// A limit set by the record's filter directive replaces the per-callsite limit.
+    /// Summaries for any windows that have ended are added to `summaries`.
+    pub(crate) fn check(
+        &self,
+        record: &Record,
+        directive: Option<RateLimit>,
+        summaries: &mut Vec<Summary>,
+    ) -> bool {
+        let now = Instant::now();
+
+        {
+            let mut callsites = lock(&self.callsites);
+
+            if now >= callsites.next_sweep {
+                callsites.sweep(now, summaries);
+            }
+
+            if let Some(limit) = directive.or(self.per_callsite) {
+                let window = callsites
+                    .windows
+                    .entry(callsite(record))
+                    .or_insert_with(|| Window::new(limit, Some(location(record)), now));
+
+                if !window.admit(now, summaries) {
+                    return false;
+                }
+            }
+        }
+
+        // Records suppressed for their callsite don't count towards the global limit
+        match self.global {
+            Some((_, ref window)) => lock(window).admit(now, summaries),
+            None => true,
+        }
+    }
+
+    /// Summarize the windows that have ended, even if no records arrived since.
+    pub(crate) fn tick(&self, summaries: &mut Vec<Summary>) {
+        let now = Instant::now();
+
+        lock(&self.callsites).sweep(now, summaries);
+
+        if let Some((_, ref window)) = self.global {
+            let mut window = lock(window);
+
+            // The window is restarted when the next record arrives
+            if window.has_ended(now) {
+                window.summarize(summaries);
+            }
+        }
+    }
+
+    /// Summarize every window with suppressed records, even if it hasn't ended.
+    pub(crate) fn flush(&self, summaries: &mut Vec<Summary>) {
+        let mut callsites = lock(&self.callsites);
+
+        for window in callsites.windows.values_mut() {
+            window.summarize(summaries);
+        }
+
+        if let Some((_, ref window)) = self.global {
+            lock(window).summarize(summaries);
+        }
+    }
+}
+
+impl Callsites {
+    /// Summarize and forget the windows that have ended.
+    fn sweep(&mut self, now: Instant, summaries: &mut Vec<Summary>) {
+        self.windows.retain(|_, window| {
+            if window.has_ended(now) {
+                window.summarize(summaries);
+                false
+            } else {
+                true
+            }
+        });
+
+        self.next_sweep = now + Duration::from_secs(SWEEP_INTERVAL_SECS);
+    }
+}
+
+impl Window {
+    fn new(limit: RateLimit, location: Option<String>, start: Instant) -> Self {
+        Window {
+            limit,
+            location,
+            start,
+            count: 0,
+            suppressed: 0,
+        }
+    }
+
+    fn has_ended(&self, now: Instant) -> bool {
+        now.duration_since(self.start) >= self.limit.period
+    }
+
+    fn admit(&mut self, now: Instant, summaries: &mut Vec<Summary>) -> bool {
+        if self.has_ended(now) {
+            self.summarize(summaries);
+            self.start = now;
+            self.count = 0;
+        }
+
+        if self.count < self.limit.records {
+            self.count += 1;
+            true
+        } else {
+            self.suppressed += 1;
+            false
+        }
+    }
+
+    fn summarize(&mut self, summaries: &mut Vec<Summary>) {
+        if self.suppressed > 0 {
+            summaries.push(Summary {
+                location: self.location.clone(),
+                suppressed: self.suppressed,
+                period: self.limit.period,
+            });
+
+            self.suppressed = 0;
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "suppressed {} records", self.suppressed)?;
+
+        if let Some(ref location) = self.location {
+            write!(f, " from {}", location)?;
+        }
+
+        write!(f, " in the last {}", Period(self.period))
+    }
+}
+
+struct Period(Duration);
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.subsec_nanos() == 0 {
+            write!(f, "{}s", self.0.as_secs())
+        } else {
+            write!(
+                f,
+                "{}ms",
+                self.0.as_secs() * 1000 + u64::from(self.0.subsec_millis())
+            )
+        }
+    }
+}
+
+/// Identify the place in the code a record was logged from.
+fn callsite(record: &Record) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    record.module_path().hash(&mut hasher);
+    record.file().hash(&mut hasher);
+    record.line().hash(&mut hasher);
+
+    hasher.finish()
+}
+
+fn location(record: &Record) -> String {
+    match (record.file(), record.line()) {
+        (Some(file), Some(line)) => format!("{}:{}", file, line),
+        (Some(file), None) => file.to_owned(),
+        _ => record.module_path().unwrap_or(record.target()).to_owned(),
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panic while holding the lock can't leave a window in an invalid state
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use log::Level;
+
+    fn check(limiter: &Limiter, line: u32, summaries: &mut Vec<Summary>) -> bool {
+        limiter.check(
+            &Record::builder()
+                .level(Level::Warn)
+                .file(Some("src/x.rs"))
+                .line(Some(line))
+                .build(),
+            None,
+            summaries,
+        )
+    }
+
+    fn rewind(window: &mut Window, by: Duration) {
+        window.start -= by;
+    }
+
+    #[test]
+    fn per_callsite_limit() {
+        let limiter = Limiter::new(None, Some(RateLimit::new(2, Duration::from_secs(10))));
+        let mut summaries = Vec::new();
+
+        assert!(check(&limiter, 88, &mut summaries));
+        assert!(check(&limiter, 88, &mut summaries));
+        assert!(!check(&limiter, 88, &mut summaries));
+        assert!(!check(&limiter, 88, &mut summaries));
+
+        // Other callsites have their own limit
+        assert!(check(&limiter, 89, &mut summaries));
+
+        assert!(summaries.is_empty());
+
+        for window in lock(&limiter.callsites).windows.values_mut() {
+            rewind(window, Duration::from_secs(10));
+        }
+
+        assert!(check(&limiter, 88, &mut summaries));
+
+        assert_eq!(1, summaries.len());
+        assert_eq!(
+            "suppressed 2 records from src/x.rs:88 in the last 10s",
+            summaries[0].to_string()
+        );
+    }
+
+    #[test]
+    fn global_limit() {
+        let limiter = Limiter::new(Some(RateLimit::new(1, Duration::from_millis(1500))), None);
+        let mut summaries = Vec::new();
+
+        assert!(check(&limiter, 1, &mut summaries));
+        assert!(!check(&limiter, 2, &mut summaries));
+
+        rewind(
+            &mut lock(&limiter.global.as_ref().unwrap().1),
+            Duration::from_secs(2),
+        );
+
+        assert!(check(&limiter, 3, &mut summaries));
+        assert_eq!(
+            vec!["suppressed 1 records in the last 1500ms".to_owned()],
+            summaries
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn directive_limit_replaces_per_callsite_limit() {
+        let limiter = Limiter::new(None, Some(RateLimit::new(1, Duration::from_secs(1))));
+        let mut summaries = Vec::new();
+
+        let record = Record::builder().line(Some(1)).build();
+        let directive = Some(RateLimit::new(3, Duration::from_secs(1)));
+
+        assert!(limiter.check(&record, directive, &mut summaries));
+        assert!(limiter.check(&record, directive, &mut summaries));
+        assert!(limiter.check(&record, directive, &mut summaries));
+        assert!(!limiter.check(&record, directive, &mut summaries));
+    }
+
+    #[test]
+    fn sweep_summarizes_ended_windows() {
+        let limiter = Limiter::new(None, Some(RateLimit::new(1, Duration::from_secs(1))));
+        let mut summaries = Vec::new();
+
+        assert!(check(&limiter, 1, &mut summaries));
+        assert!(!check(&limiter, 1, &mut summaries));
+
+        {
+            let mut callsites = lock(&limiter.callsites);
+            for window in callsites.windows.values_mut() {
+                rewind(window, Duration::from_secs(1));
+            }
+            callsites.next_sweep = Instant::now();
+        }
+
+        // A record from any callsite triggers the sweep
+        assert!(check(&limiter, 2, &mut summaries));
+
+        assert_eq!(1, summaries.len());
+        assert_eq!(1, lock(&limiter.callsites).windows.len());
+    }
+
+    #[test]
+    fn tick_summarizes_ended_windows() {
+        let limiter = Limiter::new(
+            Some(RateLimit::new(1, Duration::from_secs(1))),
+            Some(RateLimit::new(1, Duration::from_secs(60))),
+        );
+        let mut summaries = Vec::new();
+
+        assert!(check(&limiter, 1, &mut summaries));
+        assert!(!check(&limiter, 1, &mut summaries));
+        assert!(!check(&limiter, 2, &mut summaries));
+
+        limiter.tick(&mut summaries);
+        assert!(summaries.is_empty());
+
+        rewind(
+            &mut lock(&limiter.global.as_ref().unwrap().1),
+            Duration::from_secs(1),
+        );
+
+        // Only the global window has ended
+        limiter.tick(&mut summaries);
+        assert_eq!(
+            vec!["suppressed 1 records in the last 1s".to_owned()],
+            summaries
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+
+        limiter.tick(&mut summaries);
+        assert_eq!(1, summaries.len());
+    }
+
+    #[test]
+    fn flush_summarizes_every_window() {
+        let limiter = Limiter::new(
+            Some(RateLimit::new(1, Duration::from_secs(60))),
+            Some(RateLimit::new(1, Duration::from_secs(60))),
+        );
+        let mut summaries = Vec::new();
+
+        assert!(check(&limiter, 1, &mut summaries));
+        assert!(!check(&limiter, 1, &mut summaries));
+        assert!(!check(&limiter, 2, &mut summaries));
+
+        limiter.flush(&mut summaries);
+
+        let mut summaries = summaries
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        summaries.sort();
+
+        assert_eq!(
+            vec![
+                "suppressed 1 records from src/x.rs:1 in the last 60s".to_owned(),
+                "suppressed 1 records in the last 60s".to_owned(),
+            ],
+            summaries
+        );
+    }
+}
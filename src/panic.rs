@@ -0,0 +1,183 @@
+/*
+This internal module contains the panic hook.
+
+The hook is installed once, the first time a logger that needs it is initialized.
+Since it's shared by every logger, it looks up the logger records on the panicking
+thread are sent to, lets it print the panic, and then calls the hook that was
+installed before it.
+
+A panic while the thread is already logging, like in a custom format or writer,
+isn't printed, since the logger may be holding locks the hook would need.
+*/
+
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{self, Location};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::scope;
+
+thread_local! {
+    static LOGGING: Cell<bool> = Cell::new(false);
+}
+
+/// Marks the current thread as logging until it's dropped.
+pub(crate) struct Logging {
+    // Loggers can be nested, like a format that logs records of its own
+    was_logging: bool,
+}
+
+/// Mark the current thread as logging.
+pub(crate) fn logging() -> Logging {
+    Logging {
+        was_logging: LOGGING
+            .try_with(|logging| logging.replace(true))
+            .unwrap_or(true),
+    }
+}
+
+fn is_logging() -> bool {
+    LOGGING.try_with(Cell::get).unwrap_or(true)
+}
+
+impl Drop for Logging {
+    fn drop(&mut self) {
+        let was_logging = self.was_logging;
+        let _ = LOGGING.try_with(|logging| logging.set(was_logging));
+    }
+}
+
+/// Install the panic hook, if it hasn't been already.
+pub(crate) fn install_hook() {
+    static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+    if !INSTALLED.swap(true, Ordering::AcqRel) {
+        let previous = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            if !is_logging() {
+                let _logging = logging();
+                scope::with_logger(|logger| logger.panicked(info.location(), info.payload()));
+            }
+            previous(info);
+        }));
+    }
+}
+
+/// Describe a panic the way the default hook does.
+pub(crate) fn message(
+    location: Option<&Location>,
+    payload: &(dyn Any + Send),
+    backtrace: bool,
+) -> String {
+    let thread = thread::current();
+    let name = thread.name().unwrap_or("<unnamed>");
+
+    let payload = if let Some(payload) = payload.downcast_ref::<&str>() {
+        payload
+    } else if let Some(payload) = payload.downcast_ref::<String>() {
+        payload
+    } else {
+        "Box<dyn Any>"
+    };
+
+    let message = match location {
+        Some(location) => format!("thread '{}' panicked at {}:\n{}", name, location, payload),
+        None => format!("thread '{}' panicked:\n{}", name, payload),
+    };
+
+    with_backtrace(message, backtrace)
+}
+
+// The `backtrace` feature is documented as needing a newer Rust
+#[cfg(feature = "backtrace")]
+#[allow(clippy::incompatible_msrv)]
+fn with_backtrace(message: String, backtrace: bool) -> String {
+    if backtrace {
+        format!(
+            "{}\nstack backtrace:\n{}",
+            message,
+            std::backtrace::Backtrace::force_capture()
+        )
+    } else {
+        message
+    }
+}
+
+// Capturing backtraces needs a newer Rust than the crate otherwise does
+#[cfg(not(feature = "backtrace"))]
+fn with_backtrace(message: String, _: bool) -> String {
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logging_can_be_nested() {
+        assert!(!is_logging());
+
+        {
+            let _outer = logging();
+            {
+                let _inner = logging();
+                assert!(is_logging());
+            }
+            assert!(is_logging());
+        }
+
+        assert!(!is_logging());
+    }
+
+    #[test]
+    fn message_includes_thread_location_and_payload() {
+        let location = Location::caller();
+
+        let message = thread::Builder::new()
+            .name("worker".into())
+            .spawn(move || message(Some(location), &"something went wrong", false))
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            format!(
+                "thread 'worker' panicked at {}:\nsomething went wrong",
+                location
+            ),
+            message
+        );
+    }
+
+    #[test]
+    fn message_with_string_payload() {
+        let message = message(None, &String::from("formatted payload"), false);
+
+        assert!(
+            message.ends_with("panicked:\nformatted payload"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn message_with_other_payload() {
+        let message = message(None, &42, false);
+
+        assert!(message.ends_with("panicked:\nBox<dyn Any>"), "{}", message);
+    }
+
+    #[test]
+    #[cfg(feature = "backtrace")]
+    fn message_with_backtrace() {
+        let message = message(None, &"payload", true);
+
+        assert!(
+            message.contains("payload\nstack backtrace:\n"),
+            "{}",
+            message
+        );
+    }
+}
@@ -0,0 +1,318 @@
+/*
+This internal module contains the flight recorder.
+
+Records that don't match the logger's filters, but are at or above the flight
+recorder's level, are formatted into a bounded ring buffer instead of being
+discarded. Records the filters turn off on purpose are never kept. When an error
+is logged, or a thread panics, the buffered records are printed first so the
+context leading up to the error isn't lost.
+*/
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Metadata, Record};
+
+use crate::fmt::writer::{Buffer, Writer};
+
+const DEFAULT_CAPACITY: usize = 256;
+
+// Identifies the recorder that thread-local records belong to
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static RECORDED: RefCell<Vec<(usize, VecDeque<Recorded>)>> = RefCell::new(Vec::new());
+}
+
+/// Keeps recent records that were filtered out, to print when an error is logged.
+///
+/// The logger's filters decide which records are printed as usual. Records that
+/// don't match them, but are at or above the flight recorder's level, are formatted
+/// into a bounded buffer instead of being discarded. When an error is logged, or a
+/// thread panics, the buffered records are printed to the logger's target before it,
+/// so the `debug` and `trace` context leading up to the error can be seen.
+///
+/// Records that are filtered out on purpose aren't kept: a module turned `off`,
+/// or given a stricter level than the default one, stays out of the buffer. So
+/// do records rejected by the message filter.
+///
+/// When the buffer is full the oldest records are discarded. Buffered records are
+/// only printed to the logger's own target, not to any extra outputs.
+///
+/// Since filtered-out records still need to be formatted, a flight recorder makes
+/// logging at its level more expensive than usual.
+///
+/// # Examples
+///
+/// Log at `info` level, but keep the last 100 `debug` records logged on each thread:
+///
+/// ```
+/// use log::LevelFilter;
+/// use env_logger::{Builder, FlightRecorder};
+///
+/// let mut builder = Builder::new();
+///
+/// builder
+///     .filter_level(LevelFilter::Info)
+///     .flight_recorder(
+///         FlightRecorder::new(LevelFilter::Debug)
+///             .capacity(100)
+///             .per_thread(true),
+///     );
+/// ```
+#[derive(Clone, Debug)]
+pub struct FlightRecorder {
+    level: LevelFilter,
+    capacity: usize,
+    per_thread: bool,
+}
+
+impl FlightRecorder {
+    /// Keep filtered-out records down to the given level.
+    ///
+    /// Up to 256 records are kept for the whole program by default.
+    pub fn new(level: LevelFilter) -> Self {
+        FlightRecorder {
+            level,
+            capacity: DEFAULT_CAPACITY,
+            per_thread: false,
+        }
+    }
+
+    /// Set the most records to keep.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Whether to keep records for each thread separately.
+    ///
+    /// When records are kept for each thread, an error only prints the records
+    /// logged on its own thread.
+    pub fn per_thread(mut self, per_thread: bool) -> Self {
+        self.per_thread = per_thread;
+        self
+    }
+}
+
+pub(crate) struct Recorder {
+    id: usize,
+    config: FlightRecorder,
+    recorded: Mutex<VecDeque<Recorded>>,
+}
+
+/// A formatted record along with the metadata needed to print it.
+pub(crate) struct Recorded {
+    level: Level,
+    target: String,
+    module_path: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    buf: Buffer,
+}
+
+impl Recorder {
+    pub(crate) fn new(config: FlightRecorder) -> Self {
+        Recorder {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            config,
+            recorded: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The most verbose level of records to keep.
+    pub(crate) fn level(&self) -> LevelFilter {
+        self.config.level
+    }
+
+    pub(crate) fn push(&self, recorded: Recorded) {
+        let capacity = self.config.capacity;
+        if capacity == 0 {
+            return;
+        }
+
+        let push = |queue: &mut VecDeque<Recorded>| {
+            if queue.len() >= capacity {
+                queue.pop_front();
+            }
+
+            queue.push_back(recorded);
+        };
+
+        if self.config.per_thread {
+            let _ = RECORDED.try_with(|recorded| {
+                let mut recorded = recorded.borrow_mut();
+
+                match recorded.iter().position(|&(id, _)| id == self.id) {
+                    Some(i) => push(&mut recorded[i].1),
+                    None => {
+                        let mut queue = VecDeque::new();
+                        push(&mut queue);
+                        recorded.push((self.id, queue));
+                    }
+                }
+            });
+        } else {
+            push(&mut self.recorded.lock().unwrap_or_else(|e| e.into_inner()));
+        }
+    }
+
+    /// Take the records kept so far, oldest first.
+    pub(crate) fn drain(&self) -> VecDeque<Recorded> {
+        if self.config.per_thread {
+            RECORDED
+                .try_with(|recorded| {
+                    let mut recorded = recorded.borrow_mut();
+
+                    recorded
+                        .iter()
+                        .position(|&(id, _)| id == self.id)
+                        .map(|i| recorded.swap_remove(i).1)
+                        .unwrap_or_default()
+                })
+                .unwrap_or_default()
+        } else {
+            let mut recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+            mem::replace(&mut *recorded, VecDeque::new())
+        }
+    }
+}
+
+impl Recorded {
+    pub(crate) fn new(record: &Record, buf: Buffer) -> Self {
+        Recorded {
+            level: record.level(),
+            target: record.target().to_owned(),
+            module_path: record.module_path().map(Into::into),
+            file: record.file().map(Into::into),
+            line: record.line(),
+            buf,
+        }
+    }
+
+    pub(crate) fn level(&self) -> Level {
+        self.level
+    }
+
+    pub(crate) fn metadata(&self) -> Metadata<'_> {
+        Metadata::builder()
+            .level(self.level)
+            .target(&self.target)
+            .build()
+    }
+
+    pub(crate) fn print(&self, writer: &Writer) -> io::Result<()> {
+        // Writers that frame records, like syslog, need the record's metadata
+        writer.print(
+            &self.buf,
+            &Record::builder()
+                .level(self.level)
+                .target(&self.target)
+                .module_path(self.module_path.as_ref().map(String::as_str))
+                .file(self.file.as_ref().map(String::as_str))
+                .line(self.line)
+                .args(format_args!(""))
+                .build(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::fmt::writer::Builder;
+
+    fn recorded(writer: &Writer, message: &str) -> Recorded {
+        let mut buf = writer.buffer();
+        buf.write(message.as_bytes()).unwrap();
+
+        Recorded::new(
+            &Record::builder()
+                .level(Level::Debug)
+                .args(format_args!("{}", message))
+                .build(),
+            buf,
+        )
+    }
+
+    fn messages(recorded: VecDeque<Recorded>) -> Vec<String> {
+        recorded
+            .iter()
+            .map(|recorded| String::from_utf8(recorded.buf.bytes().to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_most_recent_records() {
+        let writer = Builder::new().build();
+        let recorder = Recorder::new(FlightRecorder::new(LevelFilter::Debug).capacity(2));
+
+        for message in &["a", "b", "c"] {
+            recorder.push(recorded(&writer, message));
+        }
+
+        assert_eq!(vec!["b", "c"], messages(recorder.drain()));
+        assert!(recorder.drain().is_empty());
+    }
+
+    #[test]
+    fn zero_capacity_keeps_nothing() {
+        let writer = Builder::new().build();
+        let recorder = Recorder::new(FlightRecorder::new(LevelFilter::Debug).capacity(0));
+
+        recorder.push(recorded(&writer, "a"));
+
+        assert!(recorder.drain().is_empty());
+    }
+
+    #[test]
+    fn per_thread_records_are_separate() {
+        let writer = Builder::new().build();
+        let recorder = Recorder::new(FlightRecorder::new(LevelFilter::Debug).per_thread(true));
+        let other = Recorder::new(FlightRecorder::new(LevelFilter::Debug).per_thread(true));
+
+        recorder.push(recorded(&writer, "a"));
+        other.push(recorded(&writer, "other recorder"));
+
+        let recorder = Arc::new(recorder);
+        let shared = recorder.clone();
+        thread::spawn(move || {
+            let writer = Builder::new().build();
+            shared.push(recorded(&writer, "other thread"));
+
+            assert_eq!(vec!["other thread"], messages(shared.drain()));
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(vec!["a"], messages(recorder.drain()));
+        assert_eq!(vec!["other recorder"], messages(other.drain()));
+    }
+
+    #[test]
+    fn global_records_are_shared() {
+        let writer = Builder::new().build();
+        let recorder = Recorder::new(FlightRecorder::new(LevelFilter::Debug));
+
+        recorder.push(recorded(&writer, "a"));
+
+        let recorder = Arc::new(recorder);
+        let shared = recorder.clone();
+        thread::spawn(move || {
+            let writer = Builder::new().build();
+            shared.push(recorded(&writer, "other thread"));
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(vec!["a", "other thread"], messages(recorder.drain()));
+    }
+}
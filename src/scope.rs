@@ -0,0 +1,235 @@
+/*
+This internal module contains the logger that env_logger installs globally.
+
+The installed logger dispatches each record to the logger scoped to the current
+thread, if there is one, and otherwise to the logger set by `Builder::try_init`
+or `Composite::try_init`. This lets tests in the same binary each use their own
+filters and formats, even though the `log` crate only accepts a single global
+logger.
+*/
+
+use std::cell::RefCell;
+use std::cmp;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::{tick, Composite};
+
+static DISPATCH: Dispatch = Dispatch;
+
+// Set once and never freed, so it can be used from any thread for the rest of the program.
+// It's leaked from an `Arc` so it can be ticked like scoped loggers
+static GLOBAL: AtomicPtr<Composite> = AtomicPtr::new(ptr::null_mut());
+
+// Lets records skip the thread-local lookup when there aren't any scoped loggers
+static SCOPES: AtomicUsize = AtomicUsize::new(0);
+
+// Created the first time it's locked, since `Mutex::new` can't be called in a static
+static STATE: AtomicPtr<Mutex<State>> = AtomicPtr::new(ptr::null_mut());
+
+thread_local! {
+    static SCOPED: RefCell<Vec<Arc<Composite>>> = RefCell::new(Vec::new());
+}
+
+struct State {
+    // Whether `DISPATCH` is the `log` crate's global logger
+    installed: bool,
+    scopes: usize,
+}
+
+/// A guard that keeps a logger installed for the current thread.
+///
+/// While the guard is alive, records logged on the thread that created it are
+/// sent to its logger instead of the global one. Dropping the guard flushes the
+/// logger and restores whichever logger was used before.
+///
+/// Guards are created with [`Builder::try_init_scoped`].
+///
+/// [`Builder::try_init_scoped`]: struct.Builder.html#method.try_init_scoped
+pub struct ScopeGuard {
+    logger: Arc<Composite>,
+    // The guard has to be dropped on the thread it was created on
+    _not_send: PhantomData<*const ()>,
+}
+
+struct Dispatch;
+
+/// Call `f` with the logger records on the current thread are sent to.
+pub(crate) fn with_logger<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&Composite) -> R,
+{
+    if SCOPES.load(Ordering::Acquire) > 0 {
+        let scoped = SCOPED
+            .try_with(|scoped| {
+                scoped
+                    .try_borrow()
+                    .ok()
+                    .and_then(|scoped| scoped.last().cloned())
+            })
+            .ok()
+            .and_then(|scoped| scoped);
+
+        if let Some(logger) = scoped {
+            return Some(f(&logger));
+        }
+    }
+
+    global().map(f)
+}
+
+impl Log for Dispatch {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        with_logger(|logger| logger.enabled(metadata)).unwrap_or(false)
+    }
+
+    fn log(&self, record: &Record) {
+        with_logger(|logger| logger.log(record));
+    }
+
+    fn flush(&self) {
+        with_logger(|logger| logger.flush());
+    }
+}
+
+/// The logger set by `Builder::try_init` or `Composite::try_init`, if there is one.
+pub(crate) fn global() -> Option<&'static Composite> {
+    // Safety: the pointer is either null or was leaked from an `Arc` and is never freed
+    unsafe { GLOBAL.load(Ordering::Acquire).as_ref() }
+}
+
+/// Set the global logger.
+pub(crate) fn set_global(logger: Composite) -> Result<(), SetLoggerError> {
+    let mut state = lock();
+
+    if !state.installed || global().is_some() {
+        // When the global logger has already been set this fails with the
+        // same error as setting any other logger twice
+        log::set_logger(&DISPATCH)?;
+        state.installed = true;
+    }
+
+    let logger = Arc::new(logger);
+    if logger.needs_ticks() {
+        tick::register(&logger);
+    }
+
+    let max_level = logger.max_level();
+    GLOBAL.store(Arc::into_raw(logger) as *mut Composite, Ordering::Release);
+
+    // Scoped loggers may be more verbose than the global one
+    if state.scopes == 0 {
+        log::set_max_level(max_level);
+    } else {
+        log::set_max_level(cmp::max(log::max_level(), max_level));
+    }
+
+    Ok(())
+}
+
+/// Install a logger for the current thread.
+pub(crate) fn scope(logger: Composite) -> Result<ScopeGuard, SetLoggerError> {
+    let mut state = lock();
+
+    if !state.installed {
+        log::set_logger(&DISPATCH)?;
+        state.installed = true;
+    }
+
+    let logger = Arc::new(logger);
+    if logger.needs_ticks() {
+        tick::register(&logger);
+    }
+
+    SCOPED.with(|scoped| scoped.borrow_mut().push(logger.clone()));
+
+    state.scopes += 1;
+    SCOPES.store(state.scopes, Ordering::Release);
+
+    // The max level is shared by every thread, so it can't be lowered
+    // until there are no scoped loggers left
+    log::set_max_level(cmp::max(log::max_level(), logger.max_level()));
+
+    Ok(ScopeGuard {
+        logger,
+        _not_send: PhantomData,
+    })
+}
+
+fn lock() -> MutexGuard<'static, State> {
+    let state = leaked(&STATE, || {
+        Mutex::new(State {
+            installed: false,
+            scopes: 0,
+        })
+    });
+
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Get the value `cell` points to, creating it with `init` the first time.
+///
+/// The value is leaked, so it lives for the rest of the program.
+pub(crate) fn leaked<T>(cell: &'static AtomicPtr<T>, init: fn() -> T) -> &'static T {
+    let mut value = cell.load(Ordering::Acquire);
+
+    if value.is_null() {
+        let new = Box::into_raw(Box::new(init()));
+
+        value = match cell.compare_exchange(
+            ptr::null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+            Err(existing) => {
+                // Another thread created it first
+                // Safety: `new` was leaked from a box above and never shared
+                drop(unsafe { Box::from_raw(new) });
+                existing
+            }
+        };
+    }
+
+    // Safety: the pointer was leaked from a box and is never freed
+    unsafe { &*value }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let logger = &self.logger;
+
+        // Guards may be dropped out of order, so remove this one wherever it is
+        let _ = SCOPED.try_with(|scoped| {
+            let mut scoped = scoped.borrow_mut();
+
+            if let Some(i) = scoped.iter().rposition(|other| Arc::ptr_eq(other, logger)) {
+                scoped.remove(i);
+            }
+        });
+
+        self.logger.flush();
+
+        let mut state = lock();
+        state.scopes -= 1;
+        SCOPES.store(state.scopes, Ordering::Release);
+
+        if state.scopes == 0 {
+            log::set_max_level(global().map_or(LevelFilter::Off, Composite::max_level));
+        }
+    }
+}
+
+impl fmt::Debug for ScopeGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ScopeGuard")
+            .field("logger", &self.logger)
+            .finish()
+    }
+}
@@ -0,0 +1,371 @@
+/*
+This internal module contains the counters behind logging statistics.
+
+Each logger keeps atomic counters that are updated as records are logged, so
+reading them never blocks logging. Counts for each target are only kept if
+they're asked for, because they need a lock.
+
+Only a bounded number of targets are counted, using the space-saving algorithm:
+when the table is full a new target replaces the one with the lowest count and
+takes over that count. Targets with many records are never replaced, and any
+overestimate is at most the count of the target that was replaced.
+*/
+
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use log::Level;
+
+const LEVELS: usize = 5;
+
+// How many more targets are counted than are reported
+const TARGET_SLACK: usize = 10;
+
+/// A snapshot of a logger's statistics.
+///
+/// Only records that are passed to the logger are counted. Records more verbose
+/// than the `log` crate's maximum level are discarded by the logging macros
+/// before they reach it.
+///
+/// # Examples
+///
+/// Read the statistics of the installed logger:
+///
+/// ```
+/// use log::Level;
+///
+/// env_logger::init();
+///
+/// log::error!("something went wrong");
+///
+/// if let Some(stats) = env_logger::stats() {
+///     println!("{} errors logged", stats.emitted(Level::Error));
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    enabled: [u64; LEVELS],
+    disabled: [u64; LEVELS],
+    emitted: [u64; LEVELS],
+    filtered: [u64; LEVELS],
+    rejected: u64,
+    write_errors: u64,
+    targets: Vec<(String, u64)>,
+    // How many targets are reported, so merged stats report no more
+    top: usize,
+}
+
+impl Stats {
+    /// The number of times the logger said records at `level` were enabled.
+    ///
+    /// This counts calls to `Log::enabled`, like those made by `log_enabled!`,
+    /// not records that were logged.
+    pub fn enabled(&self, level: Level) -> u64 {
+        self.enabled[index(level)]
+    }
+
+    /// The number of times the logger said records at `level` weren't enabled.
+    ///
+    /// This counts calls to `Log::enabled`, like those made by `log_enabled!`,
+    /// not records that were logged.
+    pub fn disabled(&self, level: Level) -> u64 {
+        self.disabled[index(level)]
+    }
+
+    /// The number of records at `level` that were printed.
+    pub fn emitted(&self, level: Level) -> u64 {
+        self.emitted[index(level)]
+    }
+
+    /// The number of records at `level` that weren't printed.
+    ///
+    /// This includes records filtered out by directives or sampling, and records
+    /// suppressed by rate limits or as duplicates, but not records rejected by
+    /// the message filter.
+    pub fn filtered(&self, level: Level) -> u64 {
+        self.filtered[index(level)]
+    }
+
+    /// The number of records that matched a directive, but not the message filter.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// The number of times formatting or writing a record to a target failed.
+    ///
+    /// Failures on a background writer's thread aren't counted.
+    pub fn write_errors(&self) -> u64 {
+        self.write_errors
+    }
+
+    /// The targets with the most records printed, and how many were printed.
+    ///
+    /// Targets are only counted when [`Builder::count_targets`] is set. They're
+    /// sorted from the most records to the fewest. When there are many more
+    /// targets than are reported, counts near the bottom can be overestimated.
+    ///
+    /// [`Builder::count_targets`]: struct.Builder.html#method.count_targets
+    pub fn targets(&self) -> &[(String, u64)] {
+        &self.targets
+    }
+}
+
+impl Stats {
+    /// Combine the statistics of two loggers.
+    pub(crate) fn merge(mut self, other: Stats) -> Stats {
+        for i in 0..LEVELS {
+            self.enabled[i] += other.enabled[i];
+            self.disabled[i] += other.disabled[i];
+            self.emitted[i] += other.emitted[i];
+            self.filtered[i] += other.filtered[i];
+        }
+        self.rejected += other.rejected;
+        self.write_errors += other.write_errors;
+
+        // Targets that are only in one logger's top targets are counted, but
+        // only as many are reported as the logger that reports the most
+        let mut targets: HashMap<String, u64> = self.targets.drain(..).collect();
+        for (target, count) in other.targets {
+            *targets.entry(target).or_insert(0) += count;
+        }
+        self.top = cmp::max(self.top, other.top);
+        self.targets = top_targets(targets.into_iter(), self.top);
+
+        self
+    }
+}
+
+// `AtomicUsize` is used over `AtomicU64` because not every target has 64-bit atomics
+#[derive(Default)]
+pub(crate) struct Counters {
+    enabled: [AtomicUsize; LEVELS],
+    disabled: [AtomicUsize; LEVELS],
+    emitted: [AtomicUsize; LEVELS],
+    filtered: [AtomicUsize; LEVELS],
+    rejected: AtomicUsize,
+    write_errors: AtomicUsize,
+    // The number of targets to report, and the count for each target
+    targets: Option<(usize, Mutex<HashMap<String, u64>>)>,
+}
+
+impl Counters {
+    pub(crate) fn new(targets: Option<usize>) -> Self {
+        Counters {
+            targets: targets.map(|top| (top, Mutex::new(HashMap::new()))),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn enabled(&self, level: Level, enabled: bool) {
+        let counters = if enabled {
+            &self.enabled
+        } else {
+            &self.disabled
+        };
+
+        counters[index(level)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn emitted(&self, level: Level, target: &str) {
+        self.emitted[index(level)].fetch_add(1, Ordering::Relaxed);
+
+        if let Some((top, ref targets)) = self.targets {
+            let mut targets = lock(targets);
+
+            // Avoid allocating the target for every record
+            if let Some(count) = targets.get_mut(target) {
+                *count += 1;
+                return;
+            }
+
+            if targets.len() < top.saturating_mul(TARGET_SLACK) {
+                targets.insert(target.to_owned(), 1);
+                return;
+            }
+
+            // The new target takes over the lowest count
+            let lowest = targets
+                .iter()
+                .min_by_key(|&(_, &count)| count)
+                .map(|(target, &count)| (target.clone(), count));
+
+            if let Some((lowest, count)) = lowest {
+                targets.remove(&lowest);
+                targets.insert(target.to_owned(), count + 1);
+            }
+        }
+    }
+
+    pub(crate) fn filtered(&self, level: Level) {
+        self.filtered[index(level)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn write_error(&self) {
+        self.write_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        let load = |counters: &[AtomicUsize; LEVELS]| {
+            let mut counts = [0; LEVELS];
+            for (count, counter) in counts.iter_mut().zip(counters) {
+                *count = counter.load(Ordering::Relaxed) as u64;
+            }
+            counts
+        };
+
+        Stats {
+            enabled: load(&self.enabled),
+            disabled: load(&self.disabled),
+            emitted: load(&self.emitted),
+            filtered: load(&self.filtered),
+            rejected: self.rejected.load(Ordering::Relaxed) as u64,
+            write_errors: self.write_errors.load(Ordering::Relaxed) as u64,
+            targets: match self.targets {
+                Some((top, ref targets)) => top_targets(
+                    lock(targets)
+                        .iter()
+                        .map(|(target, &count)| (target.clone(), count)),
+                    top,
+                ),
+                None => Vec::new(),
+            },
+            top: match self.targets {
+                Some((top, _)) => top,
+                None => 0,
+            },
+        }
+    }
+}
+
+fn top_targets<I>(targets: I, top: usize) -> Vec<(String, u64)>
+where
+    I: Iterator<Item = (String, u64)>,
+{
+    let mut targets: Vec<_> = targets.collect();
+
+    // Ties are broken by name so snapshots are stable
+    targets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    targets.truncate(top);
+    targets
+}
+
+fn index(level: Level) -> usize {
+    level as usize - 1
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panic while holding the lock can't leave the counts in an invalid state
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_by_level() {
+        let counters = Counters::new(None);
+
+        counters.enabled(Level::Info, true);
+        counters.enabled(Level::Debug, false);
+        counters.emitted(Level::Error, "app");
+        counters.emitted(Level::Info, "app");
+        counters.emitted(Level::Info, "app");
+        counters.filtered(Level::Trace);
+        counters.rejected();
+        counters.write_error();
+
+        let stats = counters.snapshot();
+
+        assert_eq!(1, stats.enabled(Level::Info));
+        assert_eq!(1, stats.disabled(Level::Debug));
+        assert_eq!(0, stats.enabled(Level::Debug));
+        assert_eq!(1, stats.emitted(Level::Error));
+        assert_eq!(2, stats.emitted(Level::Info));
+        assert_eq!(0, stats.emitted(Level::Trace));
+        assert_eq!(1, stats.filtered(Level::Trace));
+        assert_eq!(1, stats.rejected());
+        assert_eq!(1, stats.write_errors());
+        assert!(stats.targets().is_empty());
+    }
+
+    #[test]
+    fn reports_top_targets() {
+        let counters = Counters::new(Some(2));
+
+        for target in &["a", "b", "b", "c", "c", "c"] {
+            counters.emitted(Level::Info, target);
+        }
+
+        assert_eq!(
+            vec![("c".to_owned(), 3), ("b".to_owned(), 2)],
+            counters.snapshot().targets()
+        );
+    }
+
+    #[test]
+    fn counted_targets_are_bounded() {
+        let counters = Counters::new(Some(1));
+
+        for _ in 0..100 {
+            counters.emitted(Level::Info, "frequent");
+        }
+        for i in 0..100 {
+            counters.emitted(Level::Info, &format!("rare{}", i));
+        }
+
+        let (_, ref targets) = *counters.targets.as_ref().unwrap();
+        assert_eq!(TARGET_SLACK, lock(targets).len());
+
+        assert_eq!(
+            vec![("frequent".to_owned(), 100)],
+            counters.snapshot().targets()
+        );
+    }
+
+    #[test]
+    fn merging_combines_targets() {
+        let first = Counters::new(Some(2));
+        let second = Counters::new(Some(2));
+
+        first.emitted(Level::Warn, "a");
+        first.emitted(Level::Warn, "b");
+        second.emitted(Level::Warn, "b");
+
+        let stats = first.snapshot().merge(second.snapshot());
+
+        assert_eq!(3, stats.emitted(Level::Warn));
+        assert_eq!(
+            vec![("b".to_owned(), 2), ("a".to_owned(), 1)],
+            stats.targets()
+        );
+    }
+
+    #[test]
+    fn merging_reports_at_most_the_largest_top() {
+        let first = Counters::new(Some(1));
+        let second = Counters::new(Some(2));
+        let uncounted = Counters::new(None);
+
+        first.emitted(Level::Info, "a");
+        second.emitted(Level::Info, "b");
+        second.emitted(Level::Info, "c");
+        second.emitted(Level::Info, "c");
+
+        let stats = first
+            .snapshot()
+            .merge(second.snapshot())
+            .merge(uncounted.snapshot());
+
+        assert_eq!(
+            vec![("c".to_owned(), 2), ("a".to_owned(), 1)],
+            stats.targets()
+        );
+    }
+}
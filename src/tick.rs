@@ -0,0 +1,58 @@
+/*
+This internal module contains the ticker.
+
+Rate limit summaries and repeat counts are due once some time has passed, not
+when a record arrives. Installed loggers that produce them are registered here,
+and a background thread ticks them about once a second, so they're printed even
+if nothing else is logged.
+*/
+
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::Duration;
+
+use crate::{scope, Composite};
+
+const INTERVAL_SECS: u64 = 1;
+
+// Loggers are dropped from the list once their scope guard is dropped
+static TICKED: AtomicPtr<Mutex<Vec<Weak<Composite>>>> = AtomicPtr::new(ptr::null_mut());
+
+/// Tick a logger until it's dropped, starting the ticker if it isn't running.
+pub(crate) fn register(logger: &Arc<Composite>) {
+    static STARTED: AtomicBool = AtomicBool::new(false);
+
+    lock().push(Arc::downgrade(logger));
+
+    if !STARTED.swap(true, Ordering::AcqRel) {
+        // Without the thread summaries are still printed with the next record
+        let _ = thread::Builder::new()
+            .name("env_logger tick".into())
+            .spawn(run);
+    }
+}
+
+fn run() {
+    loop {
+        thread::sleep(Duration::from_secs(INTERVAL_SECS));
+
+        // Don't hold the lock while printing, so loggers can be registered
+        let loggers: Vec<_> = {
+            let mut ticked = lock();
+            ticked.retain(|logger| logger.upgrade().is_some());
+            ticked.iter().filter_map(Weak::upgrade).collect()
+        };
+
+        for logger in loggers {
+            logger.tick();
+        }
+    }
+}
+
+fn lock() -> MutexGuard<'static, Vec<Weak<Composite>>> {
+    let ticked = scope::leaked(&TICKED, || Mutex::new(Vec::new()));
+
+    ticked.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,148 @@
+/*
+This internal module contains the policy for errors writing log records.
+
+A logger can't return errors to the code that logged a record, so by default
+they're discarded. The policy lets them be reported instead.
+*/
+
+use std::cell::Cell;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use log::Metadata;
+
+type Callback = Arc<dyn Fn(&io::Error, &Metadata) + Send + Sync>;
+
+thread_local! {
+    static CALLING_BACK: Cell<bool> = Cell::new(false);
+}
+
+/// What to do when a log record can't be formatted or written.
+///
+/// Errors are handled for each target a record fails to be written to. Errors
+/// on a background writer's thread aren't handled.
+///
+/// # Examples
+///
+/// Count write errors in a metric:
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use env_logger::{Builder, WriteErrorPolicy};
+///
+/// static WRITE_ERRORS: AtomicUsize = AtomicUsize::new(0);
+///
+/// let mut builder = Builder::new();
+///
+/// builder.write_error_policy(WriteErrorPolicy::callback(|_error, _metadata| {
+///     WRITE_ERRORS.fetch_add(1, Ordering::Relaxed);
+/// }));
+/// ```
+#[derive(Clone)]
+pub enum WriteErrorPolicy {
+    /// Discard the error.
+    Ignore,
+    /// Print the first error to `stderr`, and discard the rest.
+    ReportOnce,
+    /// Call a function with the error and the metadata of the record that failed.
+    ///
+    /// The function can log, but errors writing the records it logs are skipped
+    /// rather than calling it again, so a failing target can't make it recurse.
+    Callback(Callback),
+    /// Panic in debug builds, and discard the error in release builds.
+    PanicInDebug,
+}
+
+impl WriteErrorPolicy {
+    /// Call `f` with each error and the metadata of the record that failed.
+    ///
+    /// See [`Callback`] for what happens if `f` logs.
+    ///
+    /// [`Callback`]: #variant.Callback
+    pub fn callback<F>(f: F) -> Self
+    where
+        F: Fn(&io::Error, &Metadata) + Send + Sync + 'static,
+    {
+        WriteErrorPolicy::Callback(Arc::new(f))
+    }
+}
+
+impl Default for WriteErrorPolicy {
+    fn default() -> Self {
+        WriteErrorPolicy::Ignore
+    }
+}
+
+impl fmt::Debug for WriteErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WriteErrorPolicy::Ignore => f.write_str("Ignore"),
+            WriteErrorPolicy::ReportOnce => f.write_str("ReportOnce"),
+            WriteErrorPolicy::Callback(_) => f.write_str("Callback(<fn>)"),
+            WriteErrorPolicy::PanicInDebug => f.write_str("PanicInDebug"),
+        }
+    }
+}
+
+pub(crate) struct WriteErrors {
+    policy: WriteErrorPolicy,
+    reported: AtomicBool,
+}
+
+impl WriteErrors {
+    pub(crate) fn new(policy: WriteErrorPolicy) -> Self {
+        WriteErrors {
+            policy,
+            reported: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn handle(&self, error: &io::Error, metadata: &Metadata) {
+        match self.policy {
+            WriteErrorPolicy::Ignore => (),
+            WriteErrorPolicy::ReportOnce => {
+                if !self.reported.swap(true, Ordering::Relaxed) {
+                    eprintln!(
+                        "warning: failed to write a log record for `{}` - {} \
+                         (later errors won't be reported)",
+                        metadata.target(),
+                        error
+                    );
+                }
+            }
+            WriteErrorPolicy::Callback(ref f) => {
+                if let Some(_calling_back) = calling_back() {
+                    f(error, metadata);
+                }
+            }
+            WriteErrorPolicy::PanicInDebug => {
+                if cfg!(debug_assertions) {
+                    panic!(
+                        "failed to write a log record for `{}` - {}",
+                        metadata.target(),
+                        error
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Marks the current thread as calling a callback until it's dropped.
+struct CallingBack;
+
+/// Mark the current thread as calling a callback, unless it already is.
+fn calling_back() -> Option<CallingBack> {
+    match CALLING_BACK.try_with(|calling_back| calling_back.replace(true)) {
+        Ok(false) => Some(CallingBack),
+        _ => None,
+    }
+}
+
+impl Drop for CallingBack {
+    fn drop(&mut self) {
+        let _ = CALLING_BACK.try_with(|calling_back| calling_back.set(false));
+    }
+}
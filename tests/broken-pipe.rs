@@ -0,0 +1,125 @@
+extern crate env_logger;
+#[macro_use]
+extern crate log;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::str;
+
+use env_logger::{BrokenPipePolicy, Output, Target};
+use log::Level;
+
+fn child(policy: &str) {
+    let policy = match policy {
+        "report" => BrokenPipePolicy::Report,
+        "disable" => BrokenPipePolicy::Disable,
+        "fallback" => BrokenPipePolicy::FallBackToStderr,
+        "exit" => BrokenPipePolicy::Exit,
+        policy => panic!("unknown policy {}", policy),
+    };
+
+    let mut builder = env_logger::Builder::new();
+    builder
+        .parse_filters("info")
+        .format(|buf, record| writeln!(buf, "{}", record.args()))
+        .target(Target::Stdout)
+        .broken_pipe_policy(policy);
+
+    // Records are still written to other outputs before exiting, including
+    // ones that buffer what they write
+    if policy == BrokenPipePolicy::Exit {
+        let file = File::create(log_file()).unwrap();
+
+        builder
+            .add_output(Output::new(Target::Stderr))
+            .add_output(Output::pipe(Box::new(BufWriter::new(file))));
+    }
+
+    builder.init();
+
+    // Wait for the parent to close the other end of stdout
+    io::stdin().read_to_end(&mut Vec::new()).unwrap();
+
+    for i in 0..3 {
+        info!("record {}", i);
+    }
+
+    let stats = env_logger::stats().unwrap();
+    eprintln!(
+        "emitted {} filtered {} write errors {}",
+        stats.emitted(Level::Info),
+        stats.filtered(Level::Info),
+        stats.write_errors()
+    );
+}
+
+fn log_file() -> PathBuf {
+    env::temp_dir().join(format!(
+        "env_logger-broken-pipe-{}",
+        env::var("LOG_FILE_ID").unwrap()
+    ))
+}
+
+fn run(policy: &str) -> (process::ExitStatus, String) {
+    let exe = env::current_exe().unwrap();
+    let mut child = Command::new(exe)
+        .env("YOU_ARE_TESTING_NOW", policy)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|e| panic!("Unable to start child process: {}", e));
+
+    drop(child.stdout.take());
+    drop(child.stdin.take());
+
+    let out = child.wait_with_output().unwrap();
+    (out.status, str::from_utf8(&out.stderr).unwrap().to_owned())
+}
+
+fn main() {
+    if let Ok(policy) = env::var("YOU_ARE_TESTING_NOW") {
+        child(&policy);
+        return;
+    }
+
+    let expected = [
+        ("report", "emitted 3 filtered 0 write errors 3\n"),
+        // Records discarded after the first error weren't printed
+        ("disable", "emitted 1 filtered 2 write errors 1\n"),
+        (
+            "fallback",
+            "record 0\nrecord 1\nrecord 2\nemitted 3 filtered 0 write errors 0\n",
+        ),
+        ("exit", "record 0\n"),
+    ];
+
+    // Children write the file output for the exit policy here
+    env::set_var("LOG_FILE_ID", process::id().to_string());
+
+    let mut failed = false;
+    for &(policy, expected) in &expected {
+        let (status, stderr) = run(policy);
+
+        if !status.success() || stderr != expected {
+            println!("test failed for {}: {}", policy, status);
+            println!("--- stderr\n{}", stderr);
+            failed = true;
+        }
+    }
+
+    let file = fs::read_to_string(log_file()).unwrap_or_default();
+    let _ = fs::remove_file(log_file());
+    if file != "record 0\n" {
+        println!("test failed for the file output with exit");
+        println!("--- file\n{}", file);
+        failed = true;
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
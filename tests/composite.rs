@@ -0,0 +1,97 @@
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuf {
+    fn take(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().split_off(0)).unwrap()
+    }
+}
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+struct Counter(Arc<AtomicUsize>);
+
+impl Log for Counter {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, _: &Record) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn flush(&self) {}
+}
+
+fn logger(level: LevelFilter, buf: &SharedBuf) -> env_logger::Logger {
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format(|buf, record| writeln!(buf, "{}", record.args()))
+        .pipe(Box::new(buf.clone()))
+        .build()
+}
+
+fn main() {
+    let info = SharedBuf::default();
+    let debug = SharedBuf::default();
+    let warnings = Counter::default();
+
+    env_logger::Composite::new()
+        .logger(logger(LevelFilter::Info, &info))
+        .logger(logger(LevelFilter::Debug, &debug))
+        .other(Box::new(warnings.clone()), LevelFilter::Warn)
+        .init();
+
+    assert_eq!(LevelFilter::Debug, log::max_level());
+
+    trace!("filtered");
+    debug!("debug");
+    info!("info");
+    warn!("warn");
+
+    assert_eq!("info\nwarn\n", info.take());
+    assert_eq!("debug\ninfo\nwarn\n", debug.take());
+    assert_eq!(1, warnings.0.load(Ordering::SeqCst));
+
+    // Statistics are added up across the env loggers
+    let stats = env_logger::stats().unwrap();
+    assert_eq!(2, stats.emitted(Level::Info));
+    assert_eq!(1, stats.emitted(Level::Debug));
+    assert_eq!(1, stats.filtered(Level::Debug));
+
+    // Scoped loggers still take over the current thread
+    let scoped = SharedBuf::default();
+    {
+        let _guard = env_logger::Builder::new()
+            .filter_level(LevelFilter::Trace)
+            .format(|buf, record| writeln!(buf, "{}", record.args()))
+            .pipe(Box::new(scoped.clone()))
+            .init_scoped();
+
+        trace!("scoped");
+    }
+    assert_eq!("scoped\n", scoped.take());
+    assert_eq!("", debug.take());
+    assert_eq!(LevelFilter::Debug, log::max_level());
+
+    assert!(env_logger::Composite::new().try_init().is_err());
+}
@@ -0,0 +1,50 @@
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+
+use std::env;
+use std::io::Write;
+use std::panic;
+use std::process;
+use std::str;
+
+use env_logger::FlightRecorder;
+use log::LevelFilter;
+
+fn main() {
+    if env::var("YOU_ARE_TESTING_NOW").is_ok() {
+        env_logger::Builder::new()
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| writeln!(buf, "{} {}", record.level(), record.args()))
+            .target(env_logger::Target::Stdout)
+            .flight_recorder(FlightRecorder::new(LevelFilter::Debug))
+            .init();
+
+        info!("started");
+        debug!("context before the panic");
+
+        let _ = panic::catch_unwind(|| panic!("something went wrong"));
+        return;
+    }
+
+    let exe = env::current_exe().unwrap();
+    let out = process::Command::new(exe)
+        .env("YOU_ARE_TESTING_NOW", "1")
+        .output()
+        .unwrap_or_else(|e| panic!("Unable to start child process: {}", e));
+
+    let stdout = str::from_utf8(&out.stdout).unwrap();
+    let stderr = str::from_utf8(&out.stderr).unwrap();
+
+    if out.status.success()
+        && stdout == "INFO started\nDEBUG context before the panic\n"
+        && stderr.contains("something went wrong")
+    {
+        return;
+    }
+
+    println!("test failed: {}", out.status);
+    println!("--- stdout\n{}", stdout);
+    println!("--- stderr\n{}", stderr);
+    process::exit(1);
+}
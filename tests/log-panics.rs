@@ -0,0 +1,68 @@
+extern crate env_logger;
+extern crate log;
+
+use std::env;
+use std::io::Write;
+use std::panic;
+use std::process;
+use std::str;
+use std::thread;
+
+fn main() {
+    if env::var("YOU_ARE_TESTING_NOW").is_ok() {
+        env_logger::Builder::new()
+            // Panics are logged even if their target is filtered out
+            .parse_filters("other=info")
+            .format(|buf, record| {
+                writeln!(
+                    buf,
+                    "{} {} {}:{} {}",
+                    record.level(),
+                    record.target(),
+                    record.file().unwrap_or("?"),
+                    record.line().unwrap_or(0),
+                    record.args()
+                )
+            })
+            .target(env_logger::Target::Stdout)
+            .log_panics(true)
+            .init();
+
+        let _ = thread::Builder::new()
+            .name("worker".into())
+            .spawn(|| panic!("something went wrong"))
+            .unwrap()
+            .join();
+
+        let _ = panic::catch_unwind(|| panic!("{} times", 2));
+        return;
+    }
+
+    let exe = env::current_exe().unwrap();
+    let out = process::Command::new(exe)
+        .env("YOU_ARE_TESTING_NOW", "1")
+        .output()
+        .unwrap_or_else(|e| panic!("Unable to start child process: {}", e));
+
+    let stdout = str::from_utf8(&out.stdout).unwrap();
+    let stderr = str::from_utf8(&out.stderr).unwrap();
+
+    let logged_worker = stdout.contains(
+        "ERROR panic tests/log-panics.rs:33 thread 'worker' panicked at tests/log-panics.rs:33:",
+    ) && stdout.contains(":\nsomething went wrong\n");
+    let logged_main = stdout.contains(
+        "ERROR panic tests/log-panics.rs:37 thread 'main' panicked at tests/log-panics.rs:37:",
+    ) && stdout.contains(":\n2 times\n");
+
+    // The default hook still runs after the logger's
+    let chained = stderr.contains("something went wrong") && stderr.contains("2 times");
+
+    if out.status.success() && logged_worker && logged_main && chained {
+        return;
+    }
+
+    println!("test failed: {}", out.status);
+    println!("--- stdout\n{}", stdout);
+    println!("--- stderr\n{}", stderr);
+    process::exit(1);
+}
@@ -0,0 +1,72 @@
+extern crate env_logger;
+extern crate log;
+
+use std::env;
+use std::io::{self, Write};
+use std::panic;
+use std::process;
+use std::str;
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct PanickingWriter;
+
+impl Write for PanickingWriter {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        panic!("the writer panicked");
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn main() {
+    if env::var("YOU_ARE_TESTING_NOW").is_ok() {
+        // The panic hook would need the writer's lock to log the panic
+        env_logger::Builder::new()
+            .filter_level(log::LevelFilter::Info)
+            .pipe(Box::new(PanickingWriter))
+            .log_panics(true)
+            .init();
+
+        let _ = panic::catch_unwind(|| log::error!("boom"));
+
+        println!("finished");
+        return;
+    }
+
+    let exe = env::current_exe().unwrap();
+    let mut child = process::Command::new(exe)
+        .env("YOU_ARE_TESTING_NOW", "1")
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|e| panic!("Unable to start child process: {}", e));
+
+    // A deadlocked child never exits
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            println!("test failed: the child process deadlocked");
+            process::exit(1);
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let out = child.wait_with_output().unwrap();
+    let stdout = str::from_utf8(&out.stdout).unwrap();
+    let stderr = str::from_utf8(&out.stderr).unwrap();
+
+    // The default hook still reports the panic
+    if out.status.success() && stdout == "finished\n" && stderr.contains("the writer panicked") {
+        return;
+    }
+
+    println!("test failed: {}", out.status);
+    println!("--- stdout\n{}", stdout);
+    println!("--- stderr\n{}", stderr);
+    process::exit(1);
+}
@@ -0,0 +1,114 @@
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+
+use std::io::{self, BufWriter, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use log::LevelFilter;
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuf {
+    fn take(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().split_off(0)).unwrap()
+    }
+}
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn builder(level: LevelFilter, buf: &SharedBuf) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(level)
+        .format(|buf, record| writeln!(buf, "{}", record.args()))
+        .pipe(Box::new(buf.clone()));
+
+    builder
+}
+
+fn main() {
+    let global = SharedBuf::default();
+    let scoped = SharedBuf::default();
+    let nested = SharedBuf::default();
+
+    // A scoped logger can be installed before the global one
+    {
+        let _guard = builder(LevelFilter::Debug, &scoped).init_scoped();
+        debug!("before global");
+    }
+    assert_eq!("before global\n", scoped.take());
+    assert_eq!(LevelFilter::Off, log::max_level());
+
+    builder(LevelFilter::Info, &global).init();
+    builder(LevelFilter::Trace, &global).try_init().unwrap_err();
+    assert_eq!(LevelFilter::Info, log::max_level());
+
+    info!("global");
+    debug!("filtered");
+    assert_eq!("global\n", global.take());
+
+    {
+        // The max level is only raised as far as the scoped logger needs
+        let _guard = builder(LevelFilter::Debug, &scoped).init_scoped();
+        assert_eq!(LevelFilter::Debug, log::max_level());
+
+        debug!("scoped");
+
+        // Other threads still use the global logger
+        thread::spawn(|| {
+            info!("other thread");
+            debug!("filtered");
+        })
+        .join()
+        .unwrap();
+
+        {
+            let _guard = builder(LevelFilter::Trace, &nested).init_scoped();
+            assert_eq!(LevelFilter::Trace, log::max_level());
+            trace!("nested");
+        }
+
+        // It isn't lowered until every scoped logger is dropped
+        assert_eq!(LevelFilter::Trace, log::max_level());
+
+        debug!("scoped again");
+    }
+
+    assert_eq!("scoped\nscoped again\n", scoped.take());
+    assert_eq!("nested\n", nested.take());
+    assert_eq!("other thread\n", global.take());
+
+    // Dropping the last guard restores the global logger and its max level
+    assert_eq!(LevelFilter::Info, log::max_level());
+    info!("global again");
+    debug!("filtered");
+    assert_eq!("global again\n", global.take());
+    assert_eq!("", scoped.take());
+
+    // Flushing the logger flushes a buffered pipe
+    {
+        let buffered = SharedBuf::default();
+        let _guard = env_logger::Builder::new()
+            .filter_level(LevelFilter::Info)
+            .format(|buf, record| writeln!(buf, "{}", record.args()))
+            .pipe(Box::new(BufWriter::new(buffered.clone())))
+            .init_scoped();
+
+        info!("buffered");
+        assert_eq!("", buffered.take());
+
+        log::logger().flush();
+        assert_eq!("buffered\n", buffered.take());
+    }
+}